@@ -0,0 +1,233 @@
+use std::{
+  collections::VecDeque,
+  sync::OnceLock,
+  env
+};
+
+use axum::{
+  http::{header, Request, StatusCode},
+  middleware::Next,
+  response::{Response, IntoResponse}
+};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::sync::Mutex;
+use tracing::log::{info, warn};
+
+use crate::notion::{
+  client::{update_types, update_page, SyncMode},
+  types::NotionDataType
+};
+
+
+pub static ADMIN_TOKENS: OnceLock<Vec<String>> = OnceLock::new();
+pub static JOB_QUEUE: OnceLock<JobQueue> = OnceLock::new();
+/// Finished jobs beyond this many are forgotten, oldest first.
+static MAX_JOBS: usize = 100;
+
+
+/// Bearer tokens accepted by the admin routes, read from `ADMIN_TOKENS` as a
+/// comma-separated list so tokens can be rotated without downtime.
+pub fn get_admin_tokens() -> &'static [String] {
+  ADMIN_TOKENS.get_or_init(
+    || {
+      env::var("ADMIN_TOKENS")
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|token| !token.is_empty())
+        .map(Into::into)
+        .collect()
+    }
+  )
+}
+
+/// Compare in time independent of where the inputs first differ.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+  a.len() == b.len()
+    && a.iter().zip(b).fold(0, |difference, (a, b)| difference | (a ^ b)) == 0
+}
+
+/// Reject requests without `Authorization: Bearer <token>` naming one of
+/// [`get_admin_tokens`].
+pub async fn require_admin_token<B>(
+  request: Request<B>,
+  next: Next<B>
+) -> Response {
+  let tokens: &[String] = get_admin_tokens();
+  if tokens.is_empty() {
+    warn!("Reject admin request: ADMIN_TOKENS is not set.");
+    return StatusCode::SERVICE_UNAVAILABLE.into_response();
+  }
+
+  let token: &[u8] = request
+    .headers()
+    .get(header::AUTHORIZATION)
+    .and_then(|value| value.as_bytes().strip_prefix(b"Bearer "))
+    .unwrap_or_default();
+  if !tokens.iter().any(|expected| constant_time_eq(expected.as_bytes(), token)) {
+    return (
+      StatusCode::UNAUTHORIZED,
+      [(header::WWW_AUTHENTICATE, "Bearer")]
+    ).into_response();
+  }
+
+  next.run(request).await
+}
+
+
+/// What a refresh job fetches.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum RefreshTarget {
+  All,
+  Type {
+    data_type: NotionDataType
+  },
+  Page {
+    data_type: NotionDataType,
+    id: String
+  }
+}
+
+impl RefreshTarget {
+  /// Fetch the target. Type refreshes fetch every row, so they also pick up
+  /// deletions and repair anything an incremental sync missed.
+  async fn refresh(self: &Self) -> Result<()> {
+    match self {
+      RefreshTarget::All => update_types(
+        &NotionDataType::iterator()
+          .map(|data_type| (data_type, SyncMode::Full))
+          .collect::<Vec<_>>()
+      ).await,
+      RefreshTarget::Type { data_type } => update_types(&[(data_type.clone(), SyncMode::Full)]).await,
+      RefreshTarget::Page { data_type, id } => update_page(data_type, id).await
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+  Queued,
+  Running,
+  Succeeded,
+  Failed
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all(serialize = "snake_case"))]
+pub struct Job {
+  pub id: String,
+  pub target: RefreshTarget,
+  pub status: JobStatus,
+  pub created_at: DateTime<Utc>,
+  pub started_at: Option<DateTime<Utc>>,
+  pub finished_at: Option<DateTime<Utc>>,
+  pub error: Option<String>
+}
+
+impl Job {
+  fn is_pending(self: &Self) -> bool {
+    matches!(self.status, JobStatus::Queued | JobStatus::Running)
+  }
+}
+
+
+/// Refresh jobs started from the admin API. A refresh of a target that
+/// already has a pending job joins that job instead of starting another.
+pub struct JobQueue {
+  jobs: Mutex<VecDeque<Job>>
+}
+
+impl JobQueue {
+  fn new() -> JobQueue {
+    JobQueue {
+      jobs: Mutex::new(VecDeque::new())
+    }
+  }
+
+  pub fn get() -> &'static JobQueue {
+    JOB_QUEUE.get_or_init(
+      JobQueue::new
+    )
+  }
+
+  pub async fn request(self: &Self, id: &str) -> Option<Job> {
+    self.jobs
+      .lock()
+      .await
+      .iter()
+      .find(|job| job.id == id)
+      .cloned()
+  }
+
+  /// Start a refresh of `target`, or return the pending job already
+  /// refreshing it.
+  pub async fn submit(self: &'static Self, target: RefreshTarget) -> Job {
+    let mut jobs = self.jobs.lock().await;
+
+    if let Some(job) = jobs.iter().find(|job| job.target == target && job.is_pending()) {
+      return job.clone();
+    }
+
+    let job: Job = Job {
+      id: hex::encode(rand::random::<[u8; 16]>()),
+      target,
+      status: JobStatus::Queued,
+      created_at: Utc::now(),
+      started_at: None,
+      finished_at: None,
+      error: None
+    };
+
+    jobs.push_back(job.clone());
+    while jobs.len() > MAX_JOBS {
+      match jobs.iter().position(|job| !job.is_pending()) {
+        Some(index) => jobs.remove(index),
+        None => break
+      };
+    }
+
+    let id: String = job.id.clone();
+    let target: RefreshTarget = job.target.clone();
+    info!("Refresh job {} queued for {:?}.", id, job.target);
+
+    tokio::spawn(
+      async move {
+        self.update(&id, |job| {
+          job.status = JobStatus::Running;
+          job.started_at = Some(Utc::now());
+        }).await;
+
+        // Run in its own task so a panic still finishes the job.
+        let result: Result<(), String> = match tokio::spawn(async move { target.refresh().await }).await {
+          Ok(Ok(())) => Ok(()),
+          Ok(Err(error)) => Err(format!("{error:#}")),
+          Err(error) => Err(error.to_string())
+        };
+
+        self.update(&id, |job| {
+          job.finished_at = Some(Utc::now());
+          match result {
+            Ok(()) => job.status = JobStatus::Succeeded,
+            Err(error) => {
+              warn!("Refresh job {} failed: {}", job.id, error);
+              job.status = JobStatus::Failed;
+              job.error = Some(error);
+            }
+          }
+        }).await;
+      }
+    );
+
+    job
+  }
+
+  async fn update(self: &Self, id: &str, change: impl FnOnce(&mut Job)) {
+    if let Some(job) = self.jobs.lock().await.iter_mut().find(|job| job.id == id) {
+      change(job);
+    }
+  }
+}
@@ -1,20 +1,35 @@
+use std::{collections::HashMap, sync::Arc};
+
 use axum::{
-  extract::Path,
-  http::{header, HeaderMap, StatusCode},
+  extract::{Path, Query},
+  http::{header, HeaderMap, HeaderValue, StatusCode, Uri},
   Json,
   response::{Response, IntoResponse}
 };
-use serde_json::json;
-use tracing::log::debug;
-
+use hyper::body::Bytes;
+use chrono::{DateTime, Utc};
+use serde_json::{Value, json};
+use tracing::log::{debug, info, warn};
+
+use crate::admin::{Job, JobQueue, RefreshTarget};
+use crate::media::{MediaStore, VariantOptions, MAX_VARIANT_SIZE};
+use crate::query::{ItemQuery, ListQuery, Projection};
+use crate::scheduler::Scheduler;
 use crate::notion::{
-  types::NotionDataType,
-  cache::CacheStorage,
-  client::fetch_data
+  types::{NotionDataType, NotionData},
+  render::ContentFormat,
+  cache::{CacheStorage, Freshness, Snapshot, Validators},
+  report::SyncReport,
+  webhook::{Debouncer, affected, get_webhook_secret, verify_signature},
+  client::normalize_page_id
 };
 
 
 static API_VERSION: &str = "1.0.0";
+static MEDIA_CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+/// Clients may reuse data for a minute, and show it for ten more while they
+/// revalidate it in the background.
+static DATA_CACHE_CONTROL: &str = "public, max-age=60, stale-while-revalidate=600";
 static ROBOTS_TXT: &str = r#"
 User-agent: *
 
@@ -22,22 +37,17 @@ Disallow: /members
 Disallow: /groups
 Disallow: /clubs
 Disallow: /sponsors
+Disallow: /admin
 "#;
 
-async fn handle_no_cache(
-  headers: &HeaderMap,
-  data_type: &NotionDataType
-) {
-  if let Some(
-    cache_control
-  ) = headers.get(header::CACHE_CONTROL) {
-    if cache_control.to_str().unwrap_or("") == "no-cache" {
-      debug!("Receive `no-cache`, cleaning cache...");
-      CacheStorage::get().update(
-        &data_type,
-        fetch_data(&data_type).await.unwrap()
-      ).await;
-    }
+
+fn render_content(
+  data: NotionData,
+  format: ContentFormat
+) -> NotionData {
+  match data {
+    NotionData::Article(article) => NotionData::Article(article.render(format)),
+    data => data
   }
 }
 
@@ -59,237 +69,453 @@ pub async fn get_version() -> Response {
   ).into_response()
 }
 
-pub async fn get_members(
-  headers: HeaderMap
+pub async fn get_media(
+  Path(hash): Path<String>,
+  Query(options): Query<VariantOptions>
 ) -> Response {
-  handle_no_cache(
-    &headers,
-    &NotionDataType::Member
-  ).await;
+  if !options.is_valid() {
+    return (
+      StatusCode::BAD_REQUEST,
+      format!("`w` and `h` must be between 1 and {MAX_VARIANT_SIZE}.")
+    ).into_response();
+  }
 
+  let media: Option<(String, Bytes)> = if options.is_original() {
+    MediaStore::get().request(&hash).await
+  } else {
+    match MediaStore::get().request_variant(&hash, &options).await {
+      Ok(media) => media,
+      Err(error) => {
+        debug!("Render variant of {} failed: {:#}", hash, error);
+        return StatusCode::UNSUPPORTED_MEDIA_TYPE.into_response();
+      }
+    }
+  };
+
+  match media {
+    Some((content_type, bytes)) => (
+      StatusCode::OK,
+      [
+        (header::CONTENT_TYPE, content_type),
+        (header::CACHE_CONTROL, MEDIA_CACHE_CONTROL.into()),
+        (header::X_CONTENT_TYPE_OPTIONS, "nosniff".into())
+      ],
+      bytes
+    ).into_response(),
+    None => StatusCode::NOT_FOUND.into_response()
+  }
+}
+
+pub async fn get_sync_report() -> Response {
   (
     StatusCode::OK,
     Json(
-      CacheStorage::get().request_all(
-        &NotionDataType::Member
-      ).await
+      SyncReport::get().request_all().await
     )
   ).into_response()
 }
 
-pub async fn get_member_by_id(
+pub async fn post_notion_webhook(
   headers: HeaderMap,
-  Path(id): Path<String>
+  body: Bytes
 ) -> Response {
-  handle_no_cache(
-    &headers,
-    &NotionDataType::Member
-  ).await;
-
-  match CacheStorage::get().request(
-    &id.to_string(),
-    &NotionDataType::Member
-  ).await {
-    Some(data) => (
-      StatusCode::OK,
-      Json(data)
-    ).into_response(),
-    None => StatusCode::NOT_FOUND.into_response()
+  let Ok(event) = serde_json::from_slice::<Value>(&body) else {
+    return StatusCode::BAD_REQUEST.into_response();
+  };
+
+  let Some(secret) = get_webhook_secret() else {
+    // Sent once when the subscription is created; the token is the secret
+    // every later event is signed with. Only expected until it is set.
+    if let Some(token) = event["verification_token"].as_str() {
+      info!("Notion webhook verification token received, set it as NOTION_WEBHOOK_SECRET. Enable debug logs to see it.");
+      debug!("Notion webhook verification token: {token}");
+      return StatusCode::OK.into_response();
+    }
+
+    warn!("Ignore Notion webhook event: NOTION_WEBHOOK_SECRET is not set.");
+    return StatusCode::SERVICE_UNAVAILABLE.into_response();
+  };
+
+  let signature: &str = headers
+    .get("X-Notion-Signature")
+    .and_then(|signature| signature.to_str().ok())
+    .unwrap_or("");
+  if !verify_signature(secret, &body, signature) {
+    return StatusCode::UNAUTHORIZED.into_response();
   }
+
+  match affected(&event) {
+    Some(refresh) => Debouncer::get().push(refresh).await,
+    None => debug!("Ignore Notion webhook event {}.", event["type"])
+  }
+
+  StatusCode::OK.into_response()
 }
 
-pub async fn get_groups(
-  headers: HeaderMap
-) -> Response {
-  handle_no_cache(
-    &headers,
-    &NotionDataType::Group
-  ).await;
+pub async fn get_freshness() -> Response {
+  (
+    StatusCode::OK,
+    Json(
+      CacheStorage::get()
+        .freshness_all()
+        .into_iter()
+        .map(
+          |(data_type, freshness)| {
+            let age: Option<i64> = freshness.age().map(|age| age.num_seconds());
+            let mut value: Value = json!(freshness);
+            value["age"] = age.into();
+
+            (data_type, value)
+          }
+        )
+        .collect::<HashMap<NotionDataType, Value>>()
+    )
+  ).into_response()
+}
 
+pub async fn get_schedule() -> Response {
   (
     StatusCode::OK,
     Json(
-      CacheStorage::get().request_all(
-        &NotionDataType::Group
-      ).await
+      Scheduler::get().request_all().await
     )
   ).into_response()
 }
 
-pub async fn get_group_by_id(
-  headers: HeaderMap,
+/// `X-Data-Age`, seconds since the records of a type were last fetched, and
+/// `X-Data-Stale`, whether they may be out of date because the latest fetch
+/// failed. Records are served either way.
+fn freshness_headers(data_type: &NotionDataType) -> HeaderMap {
+  let freshness: Freshness = CacheStorage::get().freshness(data_type);
+  let mut headers: HeaderMap = HeaderMap::new();
+
+  if let Some(age) = freshness.age() {
+    headers.insert("X-Data-Age", HeaderValue::from(age.num_seconds()));
+  }
+  headers.insert("X-Data-Stale", HeaderValue::from_static(if freshness.stale { "true" } else { "false" }));
+
+  headers
+}
+
+/// `202 Accepted` pointing at where the job can be polled.
+fn job_accepted(job: Job) -> Response {
+  (
+    StatusCode::ACCEPTED,
+    [(header::LOCATION, format!("/admin/jobs/{}", job.id))],
+    Json(job)
+  ).into_response()
+}
+
+pub async fn post_refresh_all() -> Response {
+  job_accepted(
+    JobQueue::get().submit(RefreshTarget::All).await
+  )
+}
+
+pub async fn post_refresh_type(
+  Path(data_type): Path<String>
+) -> Response {
+  let Some(data_type) = NotionDataType::from_name(&data_type) else {
+    return StatusCode::NOT_FOUND.into_response();
+  };
+
+  job_accepted(
+    JobQueue::get().submit(RefreshTarget::Type { data_type }).await
+  )
+}
+
+pub async fn post_refresh_page(
+  Path((data_type, id)): Path<(String, String)>
+) -> Response {
+  let Some(data_type) = NotionDataType::from_name(&data_type) else {
+    return StatusCode::NOT_FOUND.into_response();
+  };
+  let Some(id) = normalize_page_id(&id) else {
+    return (
+      StatusCode::BAD_REQUEST,
+      "Expected a Notion page id."
+    ).into_response();
+  };
+
+  job_accepted(
+    JobQueue::get().submit(RefreshTarget::Page { data_type, id }).await
+  )
+}
+
+pub async fn get_job(
   Path(id): Path<String>
 ) -> Response {
-  handle_no_cache(
-    &headers,
-    &NotionDataType::Group
-  ).await;
-
-  match CacheStorage::get().request(
-    &id.to_string(),
-    &NotionDataType::Group
-  ).await {
-    Some(data) => (
+  match JobQueue::get().request(&id).await {
+    Some(job) => (
       StatusCode::OK,
-      Json(data)
+      Json(job)
     ).into_response(),
     None => StatusCode::NOT_FOUND.into_response()
   }
 }
 
-pub async fn get_clubs(
+/// Whether the client's copy is current: it names the same entity tag in
+/// `If-None-Match`, or, without one, is dated no earlier than the records in
+/// `If-Modified-Since`.
+fn is_not_modified(
+  headers: &HeaderMap,
+  validators: &Validators
+) -> bool {
+  if let Some(if_none_match) = headers.get(header::IF_NONE_MATCH) {
+    return if_none_match
+      .to_str()
+      .unwrap_or("")
+      .split(',')
+      .map(|tag| tag.trim())
+      .any(|tag| tag == "*" || tag.trim_start_matches("W/").trim_matches('"') == validators.etag);
+  }
+
+  let since: Option<DateTime<Utc>> = headers
+    .get(header::IF_MODIFIED_SINCE)
+    .and_then(|since| since.to_str().ok())
+    .and_then(|since| DateTime::parse_from_rfc2822(since).ok())
+    .map(|since| since.with_timezone(&Utc));
+
+  match (since, validators.last_modified) {
+    (Some(since), Some(last_modified)) => last_modified.timestamp() <= since.timestamp(),
+    _ => false
+  }
+}
+
+/// Respond with `body` and its validators, or with `304 Not Modified` if the
+/// client's copy is current. `body` is only built when it is sent.
+fn cached_response(
+  headers: &HeaderMap,
+  data_type: &NotionDataType,
+  validators: &Validators,
+  body: impl FnOnce() -> Response
+) -> Response {
+  let mut response_headers: HeaderMap = freshness_headers(data_type);
+
+  response_headers.insert(header::CACHE_CONTROL, HeaderValue::from_static(DATA_CACHE_CONTROL));
+  if let Ok(etag) = HeaderValue::from_str(&format!("\"{}\"", validators.etag)) {
+    response_headers.insert(header::ETAG, etag);
+  }
+  if let Some(last_modified) = validators.last_modified {
+    if let Ok(last_modified) = HeaderValue::from_str(
+      &last_modified.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+    ) {
+      response_headers.insert(header::LAST_MODIFIED, last_modified);
+    }
+  }
+
+  if is_not_modified(headers, validators) {
+    return (
+      StatusCode::NOT_MODIFIED,
+      response_headers
+    ).into_response();
+  }
+
+  let mut response: Response = body();
+  response.headers_mut().extend(response_headers);
+
+  response
+}
+
+/// The records of a type that `query` asks for, in its order, with the
+/// number matching its filters in `X-Total-Count` and links to the
+/// neighbouring pages.
+fn list_response(
+  headers: &HeaderMap,
+  uri: &Uri,
+  data_type: &NotionDataType,
+  query: ListQuery
+) -> Response {
+  if let Err(message) = query.validate(data_type) {
+    return (
+      StatusCode::BAD_REQUEST,
+      message
+    ).into_response();
+  }
+
+  let snapshot: Arc<Snapshot> = CacheStorage::get().snapshot();
+  let mut validators: Validators = snapshot.collection_validators(data_type);
+  if let Some(parameters) = uri.query().filter(|parameters| !parameters.is_empty()) {
+    validators = validators.variant(parameters);
+  }
+
+  cached_response(
+    headers,
+    data_type,
+    &validators,
+    || {
+      let mut records: Vec<Value> = snapshot
+        .request_matching(data_type, &query.filters())
+        .into_iter()
+        .map(|data| json!(render_content(data, query.format)))
+        .collect();
+      let total: usize = records.len();
+
+      query.sort(&mut records);
+
+      let mut page_headers: HeaderMap = HeaderMap::new();
+      page_headers.insert("X-Total-Count", HeaderValue::from(total));
+      if let Some(links) = query
+        .links(uri.path(), uri.query(), total)
+        .and_then(|links| HeaderValue::from_str(&links).ok()) {
+        page_headers.insert(header::LINK, links);
+      }
+
+      let projection: Projection = query.projection();
+      let mut records: Vec<Value> = query.page(records);
+      for record in records.iter_mut() {
+        projection.apply(data_type, record);
+      }
+
+      (
+        page_headers,
+        Json(records)
+      ).into_response()
+    }
+  )
+}
+
+/// One record of a type, shaped as `query` asks for.
+fn item_response(
+  headers: &HeaderMap,
+  uri: &Uri,
+  data_type: &NotionDataType,
+  id: &str,
+  query: ItemQuery
+) -> Response {
+  if let Err(message) = query.projection().validate(data_type) {
+    return (
+      StatusCode::BAD_REQUEST,
+      message
+    ).into_response();
+  }
+
+  let snapshot: Arc<Snapshot> = CacheStorage::get().snapshot();
+
+  let (Some(data), Some(mut validators)) = (
+    snapshot.request(id, data_type),
+    snapshot.validators(id, data_type)
+  ) else {
+    return (
+      StatusCode::NOT_FOUND,
+      freshness_headers(data_type)
+    ).into_response();
+  };
+  if let Some(parameters) = uri.query().filter(|parameters| !parameters.is_empty()) {
+    validators = validators.variant(parameters);
+  }
+
+  cached_response(
+    headers,
+    data_type,
+    &validators,
+    || {
+      let mut record: Value = json!(render_content(data, query.format));
+      query.projection().apply(data_type, &mut record);
+
+      Json(record).into_response()
+    }
+  )
+}
+
+pub async fn get_members(
+  Query(query): Query<ListQuery>,
+  uri: Uri,
   headers: HeaderMap
 ) -> Response {
-  handle_no_cache(
-    &headers,
-    &NotionDataType::Club
-  ).await;
+  list_response(&headers, &uri, &NotionDataType::Member, query)
+}
 
-  (
-    StatusCode::OK,
-    Json(
-      CacheStorage::get().request_all(
-        &NotionDataType::Club
-      ).await
-    )
-  ).into_response()
+pub async fn get_member_by_id(
+  Path(id): Path<String>,
+  Query(query): Query<ItemQuery>,
+  uri: Uri,
+  headers: HeaderMap
+) -> Response {
+  item_response(&headers, &uri, &NotionDataType::Member, &id, query)
+}
+
+pub async fn get_groups(
+  Query(query): Query<ListQuery>,
+  uri: Uri,
+  headers: HeaderMap
+) -> Response {
+  list_response(&headers, &uri, &NotionDataType::Group, query)
+}
+
+pub async fn get_group_by_id(
+  Path(id): Path<String>,
+  Query(query): Query<ItemQuery>,
+  uri: Uri,
+  headers: HeaderMap
+) -> Response {
+  item_response(&headers, &uri, &NotionDataType::Group, &id, query)
+}
+
+pub async fn get_clubs(
+  Query(query): Query<ListQuery>,
+  uri: Uri,
+  headers: HeaderMap
+) -> Response {
+  list_response(&headers, &uri, &NotionDataType::Club, query)
 }
 
 pub async fn get_club_by_id(
-  headers: HeaderMap,
-  Path(id): Path<String>
+  Path(id): Path<String>,
+  Query(query): Query<ItemQuery>,
+  uri: Uri,
+  headers: HeaderMap
 ) -> Response {
-  handle_no_cache(
-    &headers,
-    &NotionDataType::Club
-  ).await;
-
-  match CacheStorage::get().request(
-    &id.to_string(),
-    &NotionDataType::Club
-  ).await {
-    Some(data) => (
-      StatusCode::OK,
-      Json(data)
-    ).into_response(),
-    None => StatusCode::NOT_FOUND.into_response()
-  }
+  item_response(&headers, &uri, &NotionDataType::Club, &id, query)
 }
 
 pub async fn get_events(
+  Query(query): Query<ListQuery>,
+  uri: Uri,
   headers: HeaderMap
 ) -> Response {
-  handle_no_cache(
-    &headers,
-    &NotionDataType::Event
-  ).await;
-
-  (
-    StatusCode::OK,
-    Json(
-      CacheStorage::get().request_all(
-        &NotionDataType::Event
-      ).await
-    )
-  ).into_response()
+  list_response(&headers, &uri, &NotionDataType::Event, query)
 }
 
 pub async fn get_event_by_id(
-  headers: HeaderMap,
-  Path(id): Path<String>
+  Path(id): Path<String>,
+  Query(query): Query<ItemQuery>,
+  uri: Uri,
+  headers: HeaderMap
 ) -> Response {
-  handle_no_cache(
-    &headers,
-    &NotionDataType::Event
-  ).await;
-
-  match CacheStorage::get().request(
-    &id.to_string(),
-    &NotionDataType::Event
-  ).await {
-    Some(data) => (
-      StatusCode::OK,
-      Json(data)
-    ).into_response(),
-    None => StatusCode::NOT_FOUND.into_response()
-  }
+  item_response(&headers, &uri, &NotionDataType::Event, &id, query)
 }
 
-
 pub async fn get_articles(
+  Query(query): Query<ListQuery>,
+  uri: Uri,
   headers: HeaderMap
 ) -> Response {
-  handle_no_cache(
-    &headers,
-    &NotionDataType::Article
-  ).await;
-
-  (
-    StatusCode::OK,
-    Json(
-      CacheStorage::get().request_all(
-        &NotionDataType::Article
-      ).await
-    )
-  ).into_response()
+  list_response(&headers, &uri, &NotionDataType::Article, query)
 }
 
 pub async fn get_article_by_id(
-  headers: HeaderMap,
-  Path(id): Path<String>
+  Path(id): Path<String>,
+  Query(query): Query<ItemQuery>,
+  uri: Uri,
+  headers: HeaderMap
 ) -> Response {
-  handle_no_cache(
-    &headers,
-    &NotionDataType::Article
-  ).await;
-
-  match CacheStorage::get().request(
-    &id.to_string(),
-    &NotionDataType::Article
-  ).await {
-    Some(data) => (
-      StatusCode::OK,
-      Json(data)
-    ).into_response(),
-    None => StatusCode::NOT_FOUND.into_response()
-  }
+  item_response(&headers, &uri, &NotionDataType::Article, &id, query)
 }
 
 pub async fn get_sponsors(
+  Query(query): Query<ListQuery>,
+  uri: Uri,
   headers: HeaderMap
 ) -> Response {
-  handle_no_cache(
-    &headers,
-    &NotionDataType::Sponsor
-  ).await;
-
-  (
-    StatusCode::OK,
-    Json(
-      CacheStorage::get().request_all(
-        &NotionDataType::Sponsor
-      ).await
-    )
-  ).into_response()
+  list_response(&headers, &uri, &NotionDataType::Sponsor, query)
 }
 
 pub async fn get_sponsor_by_id(
-  headers: HeaderMap,
-  Path(id): Path<String>
+  Path(id): Path<String>,
+  Query(query): Query<ItemQuery>,
+  uri: Uri,
+  headers: HeaderMap
 ) -> Response {
-  handle_no_cache(
-    &headers,
-    &NotionDataType::Sponsor
-  ).await;
-
-  match CacheStorage::get().request(
-    &id.to_string(),
-    &NotionDataType::Sponsor
-  ).await {
-    Some(data) => (
-      StatusCode::OK,
-      Json(data)
-    ).into_response(),
-    None => StatusCode::NOT_FOUND.into_response()
-  }
+  item_response(&headers, &uri, &NotionDataType::Sponsor, &id, query)
 }
@@ -1,34 +1,97 @@
+#![allow(clippy::needless_arbitrary_self_type)]
+
 use std::{
-  time::Duration,
   env,
   path::PathBuf,
   net::SocketAddr
 };
 
-use axum::{Router, routing::get, response::Redirect};
+use axum::{Router, routing::{get, post}, response::Redirect, middleware};
 use axum_server::tls_rustls::RustlsConfig;
-use notion::client::update_all;
-use tokio::time::sleep;
+use notion::{
+  cache::CacheStorage,
+  persist,
+  client::validate_schema
+};
 use tower_http::{trace::{TraceLayer, self}, cors::CorsLayer};
-use tracing::log::debug;
+use tracing::log::{info, warn, error};
 use tracing_subscriber::{
   layer::SubscriberExt,
   util::SubscriberInitExt
 };
 use dotenv::dotenv;
 
-use crate::api::*;
+use crate::{api::*, admin::require_admin_token, scheduler::Scheduler};
 
 
 mod notion;
 mod api;
+mod admin;
+mod media;
+mod scheduler;
+mod query;
+#[cfg(test)]
+mod tests;
 
 
 static HTTPS_PORT: u16 = 443;
-static MAX_CACHE_AGE: Duration = Duration::from_secs(86400);
 static GITHUB_REPO_URL: &str = "https://github.com/SCAICT/scaict-website-api";
 
 
+/// Every route of the API.
+fn app() -> Router {
+  let admin: Router = Router::new()
+    .route("/admin/sync-report", get(get_sync_report))
+    .route("/admin/schedule", get(get_schedule))
+    .route("/admin/freshness", get(get_freshness))
+    .route("/admin/refresh", post(post_refresh_all))
+    .route("/admin/refresh/:type", post(post_refresh_type))
+    .route("/admin/refresh/:type/:id", post(post_refresh_page))
+    .route("/admin/jobs/:id", get(get_job))
+    .route_layer(middleware::from_fn(require_admin_token));
+
+  Router::new()
+    .route("/version", get(get_version))
+    .route("/robots.txt", get(get_robots_txt))
+    .route("/repo", get(|| async { Redirect::permanent(GITHUB_REPO_URL) }))
+    .route("/members", get(get_members))
+    .route("/members/:id", get(get_member_by_id))
+    .route("/groups", get(get_groups))
+    .route("/groups/:id", get(get_group_by_id))
+    .route("/clubs", get(get_clubs))
+    .route("/clubs/:id", get(get_club_by_id))
+    .route("/events", get(get_events))
+    .route("/events/:id", get(get_event_by_id))
+    .route("/articles", get(get_articles))
+    .route("/articles/:id", get(get_article_by_id))
+    .route("/sponsors", get(get_sponsors))
+    .route("/sponsors/:id", get(get_sponsor_by_id))
+    .route("/media/:hash", get(get_media))
+    .route("/webhooks/notion", post(post_notion_webhook))
+    .merge(admin)
+    .layer(CorsLayer::permissive())
+    .layer(
+      TraceLayer::new_for_http()
+        .on_request(trace::DefaultOnRequest::new())
+        .on_response(trace::DefaultOnResponse::new())
+        .on_failure(trace::DefaultOnFailure::new())
+    )
+}
+
+
+/// Log every mismatch between the schema mapping and the Notion databases.
+async fn check_schema() {
+  match validate_schema().await {
+    Ok(problems) if problems.is_empty() => info!("Schema mapping matches the Notion databases."),
+    Ok(problems) => {
+      problems.iter().for_each(|problem| error!("{problem}"));
+      error!("Schema mapping does not match the Notion databases, affected rows will be skipped.");
+    },
+    Err(error) => warn!("Validate schema mapping failed: {error:#}")
+  }
+}
+
+
 #[tokio::main]
 async fn main() {
   tracing_subscriber::registry()
@@ -52,40 +115,24 @@ async fn main() {
   .await
   .unwrap();
 
+  match persist::load().await {
+    Ok(Some((generation, dataset, watermarks, synced_at, changed_at))) => {
+      CacheStorage::get().restore(generation, dataset, watermarks, synced_at, changed_at);
+      info!("Serving snapshot generation {generation} until the first sync.");
+    },
+    Ok(None) => info!("No snapshot on disk, starting empty."),
+    Err(error) => warn!("Load snapshot failed, starting empty: {error:#}")
+  }
+
+  // Checked in the background so the snapshot is served even while Notion
+  // is slow or down. Rows a mismatch breaks show up in the sync report.
+  tokio::spawn(check_schema());
+
   tokio::spawn(
-    async move {
-      update_all().await;
-      loop {
-        debug!("Updating cache...");
-        update_all().await;
-        sleep(MAX_CACHE_AGE).await;
-      }
-    }
+    Scheduler::get().run()
   );
 
-  let app: Router = Router::new()
-    .route("/version", get(get_version))
-    .route("/robots.txt", get(get_robots_txt))
-    .route("/repo", get(|| async { Redirect::permanent(GITHUB_REPO_URL) }))
-    .route("/members", get(get_members))
-    .route("/members/:id", get(get_member_by_id))
-    .route("/groups", get(get_groups))
-    .route("/groups/:id", get(get_group_by_id))
-    .route("/clubs", get(get_clubs))
-    .route("/clubs/:id", get(get_club_by_id))
-    .route("/events", get(get_events))
-    .route("/events/:id", get(get_event_by_id))
-    .route("/articles", get(get_articles))
-    .route("/articles/:id", get(get_article_by_id))
-    .route("/sponsors", get(get_sponsors))
-    .route("/sponsors/:id", get(get_sponsor_by_id))
-    .layer(CorsLayer::permissive())
-    .layer(
-      TraceLayer::new_for_http()
-        .on_request(trace::DefaultOnRequest::new())
-        .on_response(trace::DefaultOnResponse::new())
-        .on_failure(trace::DefaultOnFailure::new())
-    );
+  let app: Router = app();
 
   let addr: SocketAddr = SocketAddr::from(
     ([0, 0, 0, 0], HTTPS_PORT)
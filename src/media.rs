@@ -0,0 +1,402 @@
+use std::{
+  collections::HashMap,
+  sync::OnceLock,
+  env,
+  io::Cursor,
+  num::NonZeroUsize,
+  path::PathBuf
+};
+
+use hyper::body::Bytes;
+use image::{
+  DynamicImage,
+  ImageOutputFormat,
+  imageops::FilterType,
+  io::{Limits, Reader}
+};
+use serde::{Serialize, Deserialize};
+use sha2::{Sha256, Digest};
+use tokio::{fs, sync::{RwLock, Semaphore, SemaphorePermit}};
+use tracing::log::{debug, warn};
+use anyhow::{Result, anyhow};
+
+use crate::notion::{
+  client::download,
+  file::NotionFile,
+  types::NotionData
+};
+
+
+pub static MEDIA_STORE: OnceLock<MediaStore> = OnceLock::new();
+static INDEX_FILE_NAME: &str = "index.json";
+static VARIANTS_DIRECTORY_NAME: &str = "variants";
+static JPEG_QUALITY: u8 = 85;
+pub static MAX_VARIANT_SIZE: u32 = 4096;
+/// Sizes variants are rendered at. A requested size is rounded up to the
+/// next one, so each image has a bounded number of variants.
+static VARIANT_SIZES: [u32; 8] = [32, 64, 128, 256, 512, 1024, 2048, MAX_VARIANT_SIZE];
+/// Largest image, in pixels per side, decoded to render a variant.
+static MAX_SOURCE_SIZE: u32 = 8192;
+/// Most memory decoding one image may allocate.
+static MAX_DECODE_ALLOCATION: u64 = 256 * 1024 * 1024;
+
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MediaEntry {
+  pub hash: String,
+  pub content_type: String
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all(deserialize = "snake_case"))]
+pub enum Fit {
+  /// Crop to exactly fill the requested box.
+  Cover,
+  /// Scale to fit inside the requested box, keeping the aspect ratio.
+  #[default]
+  Contain,
+  /// Stretch to exactly the requested box.
+  Fill
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all(deserialize = "snake_case"))]
+pub enum VariantFormat {
+  Webp,
+  Png,
+  #[serde(alias = "jpg")]
+  Jpeg
+}
+
+impl VariantFormat {
+  fn from_content_type(content_type: &str) -> Option<VariantFormat> {
+    match content_type {
+      "image/webp" => Some(VariantFormat::Webp),
+      "image/png" => Some(VariantFormat::Png),
+      "image/jpeg" => Some(VariantFormat::Jpeg),
+      _ => None
+    }
+  }
+
+  fn content_type(self: &Self) -> &'static str {
+    match self {
+      VariantFormat::Webp => "image/webp",
+      VariantFormat::Png => "image/png",
+      VariantFormat::Jpeg => "image/jpeg"
+    }
+  }
+
+  fn extension(self: &Self) -> &'static str {
+    match self {
+      VariantFormat::Webp => "webp",
+      VariantFormat::Png => "png",
+      VariantFormat::Jpeg => "jpeg"
+    }
+  }
+
+  fn output_format(self: &Self) -> ImageOutputFormat {
+    match self {
+      VariantFormat::Webp => ImageOutputFormat::WebP,
+      VariantFormat::Png => ImageOutputFormat::Png,
+      VariantFormat::Jpeg => ImageOutputFormat::Jpeg(JPEG_QUALITY)
+    }
+  }
+}
+
+/// Resized and re-encoded version of a stored image.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct VariantOptions {
+  pub w: Option<u32>,
+  pub h: Option<u32>,
+  #[serde(default)]
+  pub fit: Fit,
+  pub format: Option<VariantFormat>
+}
+
+impl VariantOptions {
+  pub fn is_original(self: &Self) -> bool {
+    self.w.is_none() && self.h.is_none() && self.format.is_none()
+  }
+
+  pub fn is_valid(self: &Self) -> bool {
+    [self.w, self.h]
+      .iter()
+      .flatten()
+      .all(|size| (1..=MAX_VARIANT_SIZE).contains(size))
+  }
+
+  /// The options with each size rounded up to one of [`VARIANT_SIZES`].
+  fn snapped(self: &Self) -> VariantOptions {
+    let snap = |size: Option<u32>| size.map(
+      |size| VARIANT_SIZES
+        .iter()
+        .copied()
+        .find(|bucket| *bucket >= size)
+        .unwrap_or(MAX_VARIANT_SIZE)
+    );
+
+    VariantOptions {
+      w: snap(self.w),
+      h: snap(self.h),
+      ..self.clone()
+    }
+  }
+
+  fn file_name(
+    self: &Self,
+    hash: &str,
+    format: VariantFormat
+  ) -> String {
+    let size = |size: Option<u32>| size.map_or("auto".into(), |size| size.to_string());
+
+    format!(
+      "{hash}-{}x{}-{:?}.{}",
+      size(self.w),
+      size(self.h),
+      self.fit,
+      format.extension()
+    ).to_lowercase()
+  }
+
+  /// Resize without ever enlarging the source.
+  fn apply(
+    self: &Self,
+    image: DynamicImage
+  ) -> DynamicImage {
+    let (width, height) = (image.width(), image.height());
+
+    match (self.w.map(|w| w.min(width)), self.h.map(|h| h.min(height))) {
+      (None, None) => image,
+      (Some(w), None) => image.resize(w, u32::MAX, FilterType::CatmullRom),
+      (None, Some(h)) => image.resize(u32::MAX, h, FilterType::CatmullRom),
+      (Some(w), Some(h)) => match self.fit {
+        Fit::Cover => image.resize_to_fill(w, h, FilterType::CatmullRom),
+        Fit::Contain => image.resize(w, h, FilterType::CatmullRom),
+        Fit::Fill => image.resize_exact(w, h, FilterType::CatmullRom)
+      }
+    }
+  }
+}
+
+/// Content-addressed copy of every file referenced by the cached records,
+/// so responses never point at Notion's expiring signed urls.
+///
+/// Files are stored as `MEDIA_DIR/<sha256>`. `index.json` maps each source
+/// url (without the query string of files uploaded to Notion, which only
+/// carries the signature) to the stored file, so unchanged files are not
+/// downloaded again. Only images are mirrored.
+pub struct MediaStore {
+  directory: PathBuf,
+  base_url: String,
+  index: RwLock<HashMap<String, MediaEntry>>,
+  /// Variants rendered at once, one per core.
+  renders: Semaphore
+}
+
+impl MediaStore {
+  fn new() -> MediaStore {
+    let directory: PathBuf = PathBuf::from(
+      env::var("MEDIA_DIR").unwrap_or("media".into())
+    );
+
+    let index: HashMap<String, MediaEntry> = std::fs::read(directory.join(INDEX_FILE_NAME))
+      .ok()
+      .and_then(|index| serde_json::from_slice(&index).ok())
+      .unwrap_or_default();
+
+    MediaStore {
+      directory,
+      base_url: env::var("MEDIA_BASE_URL")
+        .unwrap_or_default()
+        .trim_end_matches('/')
+        .into(),
+      index: RwLock::new(index),
+      renders: Semaphore::new(
+        std::thread::available_parallelism().map_or(2, NonZeroUsize::get)
+      )
+    }
+  }
+
+  pub fn get() -> &'static MediaStore {
+    MEDIA_STORE.get_or_init(
+      MediaStore::new
+    )
+  }
+
+  pub fn is_valid_hash(hash: &str) -> bool {
+    hash.len() == 64 && hash.bytes().all(|byte| byte.is_ascii_hexdigit())
+  }
+
+  pub fn path(self: &Self, hash: &str) -> PathBuf {
+    self.directory.join(hash)
+  }
+
+  pub fn url(self: &Self, hash: &str) -> String {
+    format!("{}/media/{hash}", self.base_url)
+  }
+
+  /// Key of a file in the index. The signed url of a file uploaded to
+  /// Notion, the only kind that expires, changes on every fetch, so its
+  /// query string is left out; other urls are kept whole.
+  fn source_key(file: &NotionFile) -> &str {
+    match file.expiry_time {
+      Some(_) => file.url.split('?').next().unwrap_or(&file.url),
+      None => &file.url
+    }
+  }
+
+  /// Whether files of a content type are mirrored and served as such. SVG
+  /// is left out as it can carry scripts.
+  fn is_allowed_content_type(content_type: &str) -> bool {
+    let essence: &str = content_type
+      .split(';')
+      .next()
+      .unwrap_or("")
+      .trim();
+
+    essence.starts_with("image/") && essence != "image/svg+xml"
+  }
+
+  /// Stored file with the given hash, and its content type.
+  pub async fn request(
+    self: &Self,
+    hash: &str
+  ) -> Option<(String, Bytes)> {
+    if !MediaStore::is_valid_hash(hash) {
+      return None;
+    }
+
+    let content_type: String = self.index
+      .read()
+      .await
+      .values()
+      .find(|entry| entry.hash == hash)
+      .map(|entry| entry.content_type.clone())
+      .filter(|content_type| MediaStore::is_allowed_content_type(content_type))
+      .unwrap_or("application/octet-stream".into());
+
+    fs::read(self.path(hash))
+      .await
+      .ok()
+      .map(|bytes| (content_type, bytes.into()))
+  }
+
+  /// Variant of a stored image, rendered on first request and kept under
+  /// `MEDIA_DIR/variants`. Sizes are rounded up to [`VARIANT_SIZES`].
+  /// `None` when no file has this hash.
+  pub async fn request_variant(
+    self: &Self,
+    hash: &str,
+    options: &VariantOptions
+  ) -> Result<Option<(String, Bytes)>> {
+    let Some((content_type, original)) = self.request(hash).await else {
+      return Ok(None);
+    };
+
+    let options: VariantOptions = options.snapped();
+    let format: VariantFormat = options.format
+      .or(VariantFormat::from_content_type(&content_type))
+      .unwrap_or(VariantFormat::Png);
+    let path: PathBuf = self.directory
+      .join(VARIANTS_DIRECTORY_NAME)
+      .join(options.file_name(hash, format));
+
+    if let Ok(variant) = fs::read(&path).await {
+      return Ok(Some((format.content_type().into(), variant.into())));
+    }
+
+    let _permit: SemaphorePermit = self.renders.acquire().await?;
+    let variant: Vec<u8> = tokio::task::spawn_blocking(
+      move || -> Result<Vec<u8>> {
+        let mut limits: Limits = Limits::default();
+        limits.max_image_width = Some(MAX_SOURCE_SIZE);
+        limits.max_image_height = Some(MAX_SOURCE_SIZE);
+        limits.max_alloc = Some(MAX_DECODE_ALLOCATION);
+
+        let mut reader: Reader<Cursor<Bytes>> = Reader::new(Cursor::new(original)).with_guessed_format()?;
+        reader.limits(limits);
+        let image: DynamicImage = options.apply(reader.decode()?);
+        let mut variant: Cursor<Vec<u8>> = Cursor::new(Vec::new());
+
+        image.write_to(&mut variant, format.output_format())?;
+
+        Ok(variant.into_inner())
+      }
+    ).await.map_err(|error| anyhow!(error))??;
+
+    debug!("Rendered variant {:?}.", path);
+
+    fs::create_dir_all(self.directory.join(VARIANTS_DIRECTORY_NAME)).await?;
+    fs::write(&path, &variant).await?;
+
+    Ok(Some((format.content_type().into(), variant.into())))
+  }
+
+  async fn store(
+    self: &Self,
+    file: &NotionFile
+  ) -> Result<MediaEntry> {
+    let source_key: &str = MediaStore::source_key(file);
+
+    if let Some(entry) = self.index.read().await.get(source_key) {
+      if fs::try_exists(self.path(&entry.hash)).await.unwrap_or(false) {
+        return Ok(entry.clone());
+      }
+    }
+
+    let (content_type, bytes) = download(&file.url).await?;
+    if !MediaStore::is_allowed_content_type(&content_type) {
+      return Err(anyhow!("Refusing to mirror `{content_type}`, expected an image."));
+    }
+    let hash: String = hex::encode(Sha256::digest(&bytes));
+
+    fs::create_dir_all(&self.directory).await?;
+    fs::write(self.path(&hash), &bytes).await?;
+
+    debug!("Mirrored {} as {}.", source_key, hash);
+
+    let entry: MediaEntry = MediaEntry { hash, content_type };
+
+    let mut index = self.index.write().await;
+    index.insert(source_key.into(), entry.clone());
+    fs::write(
+      self.directory.join(INDEX_FILE_NAME),
+      serde_json::to_vec(&*index)?
+    ).await?;
+
+    Ok(entry)
+  }
+
+  /// Download a file into the store and point it at the local copy. On
+  /// failure the file keeps its original url.
+  pub async fn mirror(
+    self: &Self,
+    file: &mut NotionFile
+  ) {
+    if file.url.is_empty() || file.url.starts_with(&self.url("")) {
+      return;
+    }
+
+    match self.store(file).await {
+      Ok(entry) => {
+        file.url = self.url(&entry.hash);
+        file.expiry_time = None;
+      },
+      Err(error) => warn!(
+        "Mirror {} failed, keeping the original url: {:#}",
+        MediaStore::source_key(file), error
+      )
+    }
+  }
+
+  pub async fn mirror_all(
+    self: &Self,
+    data: &mut [NotionData]
+  ) {
+    for record in data.iter_mut() {
+      for file in record.files_mut() {
+        self.mirror(file).await;
+      }
+    }
+  }
+}
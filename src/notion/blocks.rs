@@ -0,0 +1,195 @@
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+use anyhow::{Result, anyhow};
+
+use super::{
+  file::NotionFile,
+  rich_text::{self, RichText}
+};
+
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BlockKind {
+  Paragraph {
+    rich_text: RichText
+  },
+  Heading {
+    level: u8,
+    rich_text: RichText,
+    is_toggleable: bool
+  },
+  BulletedListItem {
+    rich_text: RichText
+  },
+  NumberedListItem {
+    rich_text: RichText
+  },
+  ToDo {
+    rich_text: RichText,
+    checked: bool
+  },
+  Quote {
+    rich_text: RichText
+  },
+  Code {
+    text: String,
+    language: String,
+    caption: RichText
+  },
+  Image {
+    url: NotionFile,
+    caption: RichText
+  },
+  Callout {
+    rich_text: RichText,
+    icon: Option<String>
+  },
+  Toggle {
+    rich_text: RichText
+  },
+  Divider,
+  Table {
+    has_column_header: bool,
+    has_row_header: bool,
+    rows: Vec<Vec<RichText>>
+  },
+  TableRow {
+    cells: Vec<RichText>
+  },
+  Unsupported {
+    block_type: String
+  }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all(serialize = "snake_case"))]
+pub struct Block {
+  pub id: String,
+  #[serde(flatten)]
+  pub kind: BlockKind,
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub children: Vec<Block>
+}
+
+impl Block {
+  /// Whether the children of this block belong to it. Child pages and
+  /// databases are separate documents and are not fetched.
+  pub fn should_fetch_children(json_data: &Value) -> bool {
+    json_data["has_children"].as_bool().unwrap_or(false)
+      && !matches!(
+        json_data["type"].as_str(),
+        Some("child_page") | Some("child_database")
+      )
+  }
+
+  pub fn from_json(
+    json_data: &Value,
+    mut children: Vec<Block>
+  ) -> Result<Block> {
+    let block_type: &str = json_data["type"]
+      .as_str()
+      .ok_or(
+        anyhow!("Get `type` failed.")
+      )?;
+    let content: &Value = &json_data[block_type];
+    let text: RichText = rich_text::spans(&content["rich_text"]);
+
+    let kind: BlockKind = match block_type {
+      "paragraph" => BlockKind::Paragraph { rich_text: text },
+      "heading_1" | "heading_2" | "heading_3" => BlockKind::Heading {
+        level: block_type[block_type.len() - 1..].parse()?,
+        rich_text: text,
+        is_toggleable: content["is_toggleable"].as_bool().unwrap_or(false)
+      },
+      "bulleted_list_item" => BlockKind::BulletedListItem { rich_text: text },
+      "numbered_list_item" => BlockKind::NumberedListItem { rich_text: text },
+      "to_do" => BlockKind::ToDo {
+        rich_text: text,
+        checked: content["checked"].as_bool().unwrap_or(false)
+      },
+      "quote" => BlockKind::Quote { rich_text: text },
+      "code" => BlockKind::Code {
+        text: rich_text::plain_text(&text),
+        language: content["language"].as_str().unwrap_or("plain text").into(),
+        caption: rich_text::spans(&content["caption"])
+      },
+      "image" => BlockKind::Image {
+        url: NotionFile::from_json(content, "image")?,
+        caption: rich_text::spans(&content["caption"])
+      },
+      "callout" => BlockKind::Callout {
+        rich_text: text,
+        icon: content["icon"]["emoji"].as_str().map(Into::into)
+      },
+      "toggle" => BlockKind::Toggle { rich_text: text },
+      "divider" => BlockKind::Divider,
+      "table" => BlockKind::Table {
+        has_column_header: content["has_column_header"].as_bool().unwrap_or(false),
+        has_row_header: content["has_row_header"].as_bool().unwrap_or(false),
+        rows: children
+          .drain(..)
+          .filter_map(
+            |row| match row.kind {
+              BlockKind::TableRow { cells } => Some(cells),
+              _ => None
+            }
+          )
+          .collect()
+      },
+      "table_row" => BlockKind::TableRow {
+        cells: content["cells"]
+          .as_array()
+          .ok_or(
+            anyhow!("Get `table_row.cells` failed.")
+          )?
+          .iter()
+          .map(rich_text::spans)
+          .collect()
+      },
+      _ => BlockKind::Unsupported { block_type: block_type.into() }
+    };
+
+    Ok(
+      Block {
+        id: Block::get_id(json_data)?,
+        kind,
+        children
+      }
+    )
+  }
+
+  /// Every file referenced by this block and its descendants.
+  pub fn files(self: &Self) -> Vec<&NotionFile> {
+    let mut files: Vec<&NotionFile> = match &self.kind {
+      BlockKind::Image { url, .. } => vec![url],
+      _ => Vec::new()
+    };
+
+    files.extend(self.children.iter().flat_map(Block::files));
+
+    files
+  }
+
+  pub fn files_mut(self: &mut Self) -> Vec<&mut NotionFile> {
+    let mut files: Vec<&mut NotionFile> = match &mut self.kind {
+      BlockKind::Image { url, .. } => vec![url],
+      _ => Vec::new()
+    };
+
+    files.extend(self.children.iter_mut().flat_map(Block::files_mut));
+
+    files
+  }
+
+  fn get_id(json_data: &Value) -> Result<String> {
+    Ok(
+      json_data["id"]
+        .as_str()
+        .ok_or(
+          anyhow!("Get `id` failed.")
+        )?
+        .into()
+    )
+  }
+}
@@ -1,96 +1,496 @@
 use std::{
   collections::HashMap,
-  sync::OnceLock
+  sync::{Arc, OnceLock, RwLock}
 };
 
-use tokio::sync::{
-  RwLock,
-  RwLockReadGuard,
-  RwLockWriteGuard
-};
+use arc_swap::ArcSwap;
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
 
-use super::types::{NotionDataType, NotionData};
+use super::{
+  types::{NotionDataType, NotionData},
+  relations::{self, Dataset},
+  index::{Filter, Index}
+};
 
 
 pub static CACHE_STORAGE: OnceLock<CacheStorage> = OnceLock::new();
 
 
-pub struct CacheStorage {
-  data: RwLock<HashMap<NotionDataType, HashMap<String, NotionData>>>
+/// Newest `last_edited_time` seen in each database.
+pub type Watermarks = HashMap<NotionDataType, DateTime<Utc>>;
+
+/// When each type was last fetched successfully.
+pub type SyncTimes = HashMap<NotionDataType, DateTime<Utc>>;
+
+/// When the records of each type last changed, including removals.
+pub type ChangeTimes = HashMap<NotionDataType, DateTime<Utc>>;
+
+
+/// How current the served records of one type are.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all(serialize = "snake_case"))]
+pub struct Freshness {
+  /// Start of the latest fetch of the type that succeeded.
+  pub synced_at: Option<DateTime<Utc>>,
+  /// Whether the records may be out of date: the latest fetch failed, or
+  /// none has succeeded since the server started.
+  pub stale: bool,
+  /// Why the latest fetch failed.
+  pub error: Option<String>
 }
 
-impl CacheStorage {
-  fn new() -> CacheStorage {
-    let mut init_hashmap: HashMap<_, _> = HashMap::new();
+impl Default for Freshness {
+  fn default() -> Freshness {
+    Freshness {
+      synced_at: None,
+      stale: true,
+      error: None
+    }
+  }
+}
+
+impl Freshness {
+  /// Time since [`Freshness::synced_at`].
+  pub fn age(self: &Self) -> Option<Duration> {
+    self.synced_at.map(|synced_at| (Utc::now() - synced_at).max(Duration::zero()))
+  }
+}
+
+
+/// What conditional requests for a record or a collection are checked
+/// against.
+#[derive(Debug, Clone)]
+pub struct Validators {
+  /// Strong entity tag, without the quotes.
+  pub etag: String,
+  /// Newest edit of the records, including embedded records. For a
+  /// collection, when its records last changed, which a removal moves
+  /// forward too. `None` for an empty collection.
+  pub last_modified: Option<DateTime<Utc>>
+}
+
+impl Validators {
+  /// Validators of a different representation of the same records, such as
+  /// an article rendered to HTML.
+  pub fn variant(self: &Self, name: &str) -> Validators {
+    Validators {
+      etag: digest([self.etag.as_str(), name]),
+      last_modified: self.last_modified
+    }
+  }
+}
+
+/// First 128 bits of the SHA-256 of `parts`, in hex.
+fn digest<'a>(parts: impl IntoIterator<Item = &'a str>) -> String {
+  let mut hasher: Sha256 = Sha256::new();
+
+  for part in parts {
+    hasher.update(part.len().to_le_bytes());
+    hasher.update(part);
+  }
+
+  hex::encode(&hasher.finalize()[..16])
+}
+
+/// Validators of the records of one type, derived from the validators of
+/// each record.
+fn collection_validators<'a>(
+  records: impl Iterator<Item = (&'a String, &'a Validators)>
+) -> Validators {
+  let mut records: Vec<(&String, &Validators)> = records.collect();
+  records.sort_by_key(|(id, _)| *id);
+
+  Validators {
+    etag: digest(
+      records
+        .iter()
+        .flat_map(|(id, validators)| [id.as_str(), validators.etag.as_str()])
+    ),
+    last_modified: records
+      .iter()
+      .filter_map(|(_, validators)| validators.last_modified)
+      .max()
+  }
+}
+
+/// When a collection last changed, given its validators in the previous
+/// snapshot. A change that brings no newer edit, such as a removed record,
+/// counts as happening now.
+fn last_changed(
+  previous: Option<&Validators>,
+  current: &Validators,
+  now: DateTime<Utc>
+) -> Option<DateTime<Utc>> {
+  let Some(previous) = previous else {
+    return current.last_modified;
+  };
+
+  if previous.etag == current.etag {
+    return previous.last_modified;
+  }
+
+  match (previous.last_modified, current.last_modified) {
+    (Some(previous), Some(current)) if current > previous => Some(current),
+    (None, current) => current,
+    _ => Some(now)
+  }
+}
+
+
+/// One complete, immutable view of every data type.
+#[derive(Debug, Default)]
+pub struct Snapshot {
+  /// Increases by one with every published snapshot; 0 is the empty
+  /// snapshot the server starts with.
+  pub generation: u64,
+  data: HashMap<NotionDataType, HashMap<String, NotionData>>,
+  validators: HashMap<NotionDataType, HashMap<String, Validators>>,
+  collection_validators: HashMap<NotionDataType, Validators>,
+  indexes: HashMap<NotionDataType, Index>,
+  expiry_time: HashMap<NotionDataType, DateTime<Utc>>,
+  watermarks: Watermarks
+}
+
+impl Snapshot {
+  fn new(
+    generation: u64,
+    dataset: Dataset,
+    watermarks: Watermarks,
+    previous: &Snapshot
+  ) -> Snapshot {
+    let now: DateTime<Utc> = Utc::now();
+    let mut data: HashMap<NotionDataType, HashMap<String, NotionData>> = HashMap::new();
+    let mut validators: HashMap<NotionDataType, HashMap<String, Validators>> = HashMap::new();
+    let mut collections: HashMap<NotionDataType, Validators> = HashMap::new();
+    let mut indexes: HashMap<NotionDataType, Index> = HashMap::new();
+    let mut expiry_time: HashMap<NotionDataType, DateTime<Utc>> = HashMap::new();
+
+    for (data_type, records) in dataset {
+      let record_validators: HashMap<String, Validators> = records
+        .iter()
+        .map(
+          |record| {
+            let json: String = serde_json::to_string(record).unwrap_or_default();
+            let validators: Validators = Validators {
+              etag: digest([json.as_str()]),
+              last_modified: Some(record.last_edited_time())
+            };
 
-    NotionDataType::iterator().for_each(
-      |data_type| {
-        init_hashmap.insert(data_type, HashMap::new());
+            (record.id().into(), validators)
+          }
+        )
+        .collect();
+      let mut collection: Validators = collection_validators(record_validators.iter());
+      collection.last_modified = last_changed(
+        previous.collection_validators.get(&data_type),
+        &collection,
+        now
+      );
+      collections.insert(data_type.clone(), collection);
+      validators.insert(data_type.clone(), record_validators);
+      indexes.insert(data_type.clone(), Index::new(records.iter()));
+
+      if let Some(time) = records
+        .iter()
+        .flat_map(NotionData::files)
+        .filter_map(|file| file.expiry_time)
+        .min() {
+        expiry_time.insert(data_type.clone(), time);
       }
-    );
 
+      data.insert(
+        data_type,
+        records
+          .into_iter()
+          .map(|record| (record.id().into(), record))
+          .collect()
+      );
+    }
+
+    Snapshot {
+      generation,
+      data,
+      validators,
+      collection_validators: collections,
+      indexes,
+      expiry_time,
+      watermarks
+    }
+  }
+
+  pub fn validators(
+    self: &Self,
+    id: &str,
+    data_type: &NotionDataType
+  ) -> Option<Validators> {
+    self.validators.get(data_type)?.get(id).cloned()
+  }
+
+  /// Validators of every record of a type taken together.
+  pub fn collection_validators(
+    self: &Self,
+    data_type: &NotionDataType
+  ) -> Validators {
+    self.collection_validators
+      .get(data_type)
+      .cloned()
+      .unwrap_or_else(|| collection_validators(std::iter::empty()))
+  }
+
+  pub fn request(
+    self: &Self,
+    id: &str,
+    data_type: &NotionDataType
+  ) -> Option<NotionData> {
+    self.data.get(data_type)?.get(id).cloned()
+  }
+
+  pub fn request_all(
+    self: &Self,
+    data_type: &NotionDataType
+  ) -> Vec<NotionData> {
+    self.data
+      .get(data_type)
+      .map(|records| records.values().cloned().collect())
+      .unwrap_or_default()
+  }
+
+  /// Records of a type matching every filter, looked up in the type's
+  /// index. Every record when there are no filters.
+  pub fn request_matching(
+    self: &Self,
+    data_type: &NotionDataType,
+    filters: &[Filter]
+  ) -> Vec<NotionData> {
+    let Some(ids) = self.indexes
+      .get(data_type)
+      .and_then(|index| index.matching(filters)) else {
+      return match filters.is_empty() {
+        true => self.request_all(data_type),
+        false => Vec::new()
+      };
+    };
+
+    ids
+      .iter()
+      .filter_map(|id| self.request(id, data_type))
+      .collect()
+  }
+
+  /// Every record, grouped by type.
+  pub fn records(
+    self: &Self
+  ) -> impl Iterator<Item = (&NotionDataType, Vec<&NotionData>)> {
+    self.data
+      .iter()
+      .map(|(data_type, records)| (data_type, records.values().collect()))
+  }
+
+  /// Only rows edited on or after this time need fetching again. `None`
+  /// until the type has been fetched in full.
+  pub fn watermark(self: &Self, data_type: &NotionDataType) -> Option<DateTime<Utc>> {
+    self.watermarks.get(data_type).copied()
+  }
+
+  pub fn watermarks(self: &Self) -> &Watermarks {
+    &self.watermarks
+  }
+
+  /// When the records of each type last changed.
+  pub fn changed_at(self: &Self) -> ChangeTimes {
+    self.collection_validators
+      .iter()
+      .filter_map(
+        |(data_type, validators)| Some((data_type.clone(), validators.last_modified?))
+      )
+      .collect()
+  }
+
+  /// Earliest time a Notion-hosted file url of a type stops working.
+  pub fn expiry_time(self: &Self, data_type: &NotionDataType) -> Option<DateTime<Utc>> {
+    self.expiry_time.get(data_type).copied()
+  }
+}
+
+
+/// The published snapshot, and how fresh each of its types is. A failed
+/// fetch only marks its type stale; the records stay as they were.
+pub struct CacheStorage {
+  current: ArcSwap<Snapshot>,
+  freshness: RwLock<HashMap<NotionDataType, Freshness>>
+}
+
+impl CacheStorage {
+  fn new() -> CacheStorage {
     CacheStorage {
-      data: RwLock::new(init_hashmap)
+      current: ArcSwap::from_pointee(Snapshot::default()),
+      freshness: RwLock::new(HashMap::new())
     }
   }
 
   pub fn get() -> &'static CacheStorage {
     CACHE_STORAGE.get_or_init(
-      || CacheStorage::new()
+      CacheStorage::new
     )
   }
 
-  pub async fn request(
+  /// The current snapshot. Handlers should load it once and read every
+  /// record from it, so one response never mixes two generations.
+  pub fn snapshot(self: &Self) -> Arc<Snapshot> {
+    self.current.load_full()
+  }
+
+  /// Replace every data type at once with a new generation. Types missing
+  /// from `dataset` are left empty, so callers pass the complete dataset.
+  pub fn publish(
+    self: &Self,
+    dataset: Dataset,
+    watermarks: Watermarks
+  ) -> u64 {
+    let current: Arc<Snapshot> = self.snapshot();
+    let generation: u64 = current.generation + 1;
+
+    self.current.store(Arc::new(Snapshot::new(generation, dataset, watermarks, &current)));
+
+    generation
+  }
+
+  /// Publish a new generation with one record replaced, added, or removed
+  /// when `record` is `None`, and relations resolved again. Returns the new
+  /// generation, or `None` if the record was already as given.
+  pub fn upsert(
     self: &Self,
-    id: &str,
     data_type: &NotionDataType,
-  ) -> Option<NotionData> {
-    let storage: RwLockReadGuard<_> = self.data.read().await;
+    id: &str,
+    record: Option<NotionData>
+  ) -> Option<u64> {
+    let current: Arc<Snapshot> = self.snapshot();
+
+    let cached: Option<NotionData> = current.request(id, data_type);
+    let unchanged: bool = match (&cached, &record) {
+      (None, None) => true,
+      (Some(cached), Some(record)) => {
+        serde_json::to_value(relations::unresolved(cached)).ok()
+          == serde_json::to_value(record).ok()
+      },
+      _ => false
+    };
+    if unchanged {
+      return None;
+    }
 
-    match storage.get(&data_type).unwrap().get(id) {
-      Some(data) => Some(data.clone()),
-      None => None
+    let mut dataset: Dataset = current.data
+      .iter()
+      .map(
+        |(data_type, records)| (
+          data_type.clone(),
+          records
+            .values()
+            .filter(|cached| cached.id() != id)
+            .cloned()
+            .collect::<Vec<NotionData>>()
+        )
+      )
+      .collect();
+    if let Some(record) = record {
+      dataset.entry(data_type.clone()).or_default().push(record);
     }
+
+    relations::resolve(&mut dataset);
+
+    Some(self.publish(dataset, current.watermarks.clone()))
   }
 
-  pub async fn request_all(
+  /// Serve a snapshot read back from disk, keeping its generation and when
+  /// its types last changed. Its types count as stale until they are
+  /// fetched again.
+  pub fn restore(
     self: &Self,
-    data_type: &NotionDataType
-  ) -> Vec<NotionData> {
-    let storage: RwLockReadGuard<_> = self.data.read().await;
+    generation: u64,
+    dataset: Dataset,
+    watermarks: Watermarks,
+    synced_at: SyncTimes,
+    changed_at: ChangeTimes
+  ) {
+    let mut snapshot: Snapshot = Snapshot::new(generation, dataset, watermarks, &Snapshot::default());
+    for (data_type, validators) in snapshot.collection_validators.iter_mut() {
+      validators.last_modified = validators.last_modified.max(changed_at.get(data_type).copied());
+    }
 
-    let mut result: Vec<NotionData> = Vec::new();
+    self.current.store(Arc::new(snapshot));
 
-    for data in storage.get(&data_type).unwrap().values().into_iter() {
-      result.push(data.clone());
+    let mut freshness = self.freshness.write().unwrap();
+    for (data_type, synced_at) in synced_at {
+      freshness.insert(
+        data_type,
+        Freshness {
+          synced_at: Some(synced_at),
+          ..Freshness::default()
+        }
+      );
     }
+  }
 
-    result
+  pub fn freshness(self: &Self, data_type: &NotionDataType) -> Freshness {
+    self.freshness
+      .read()
+      .unwrap()
+      .get(data_type)
+      .cloned()
+      .unwrap_or_default()
   }
 
-  pub async fn update(
+  /// Freshness of every type, including those never fetched.
+  pub fn freshness_all(self: &Self) -> HashMap<NotionDataType, Freshness> {
+    NotionDataType::iterator()
+      .map(
+        |data_type| {
+          let freshness: Freshness = self.freshness(&data_type);
+          (data_type, freshness)
+        }
+      )
+      .collect()
+  }
+
+  pub fn synced_at(self: &Self) -> SyncTimes {
+    self.freshness
+      .read()
+      .unwrap()
+      .iter()
+      .filter_map(
+        |(data_type, freshness)| Some((data_type.clone(), freshness.synced_at?))
+      )
+      .collect()
+  }
+
+  /// Record that a fetch of a type started at `started_at` succeeded.
+  pub fn mark_synced(
     self: &Self,
     data_type: &NotionDataType,
-    new_data: Vec<NotionData>
+    started_at: DateTime<Utc>
   ) {
-    let mut storage: RwLockWriteGuard<_> = self.data.write().await;
-
-    let cache: &mut HashMap<String, NotionData> = storage.get_mut(&data_type).unwrap();
-    cache.clear();
-
-    new_data
-      .into_iter()
-      .for_each(
-        |raw_data: NotionData| {
-          match &raw_data {
-            NotionData::Member(data) => { cache.insert(data.id.clone(), raw_data); },
-            NotionData::Group(data) => { cache.insert(data.id.clone(), raw_data); },
-            NotionData::Club(data) => { cache.insert(data.id.clone(), raw_data); },
-            NotionData::Event(data) => { cache.insert(data.id.clone(), raw_data); },
-            NotionData::Article(data) => { cache.insert(data.id.clone(), raw_data); },
-            NotionData::Sponsor(data) => { cache.insert(data.id.clone(), raw_data); }
-          }
-        }
-      );
+    self.freshness.write().unwrap().insert(
+      data_type.clone(),
+      Freshness {
+        synced_at: Some(started_at),
+        stale: false,
+        error: None
+      }
+    );
+  }
+
+  /// Record that a fetch of a type failed, keeping its records.
+  pub fn mark_failed(
+    self: &Self,
+    data_type: &NotionDataType,
+    error: String
+  ) {
+    let mut freshness = self.freshness.write().unwrap();
+    let entry: &mut Freshness = freshness.entry(data_type.clone()).or_default();
+
+    entry.stale = true;
+    entry.error = Some(error);
   }
 }
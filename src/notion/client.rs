@@ -1,10 +1,15 @@
 use std::{
+  collections::{HashMap, HashSet, hash_map::Entry},
+  future::Future,
+  pin::Pin,
   sync::{OnceLock, Arc},
   env,
   io::Read,
+  net::IpAddr,
   time::Duration
 };
 
+use chrono::{DateTime, SecondsFormat, Utc};
 use flate2::read::GzDecoder;
 use hyper::{
   Client,
@@ -14,18 +19,23 @@ use hyper::{
   http::request::Builder,
   header,
   Response,
-  body
+  StatusCode,
+  Method,
+  Uri,
+  body::{self, Bytes, HttpBody}
 };
 use hyper_rustls::{
   HttpsConnector as rustls_HttpsConnector,
   HttpsConnectorBuilder
 };
-use serde_json::Value;
+use serde_json::{Value, json};
 use anyhow::{Result, anyhow};
-use tokio::time::sleep;
-use tracing::log::debug;
+use tokio::{sync::{Mutex, MutexGuard}, time::{sleep, timeout}};
+use tracing::log::{debug, info, warn, error};
 
-use super::{types::{
+use crate::media::MediaStore;
+
+use super::{blocks::Block, error::NotionError, retry::RetryPolicy, types::{
   Member,
   Group,
   Club,
@@ -33,8 +43,9 @@ use super::{types::{
   NotionData,
   Event,
   Article,
+  ArticleContent,
   Sponsor
-}, cache::CacheStorage};
+}, cache::{CacheStorage, Snapshot, Watermarks}, report::{SyncReport, ParseDiagnostic}, schema::Schema, relations::{self, Dataset}, persist, source::{self, DataSource, SourceFuture}};
 
 
 type HttpsConnector = rustls_HttpsConnector<HttpConnector>;
@@ -42,7 +53,15 @@ type HttpsConnector = rustls_HttpsConnector<HttpConnector>;
 
 static HTTP_CLIENT: OnceLock<Client<HttpsConnector, Body>> = OnceLock::new();
 static INTEGRATION_SECRET: OnceLock<Arc<str>> = OnceLock::new();
+static API_BASE_URL: OnceLock<Arc<str>> = OnceLock::new();
+static DEFAULT_API_BASE_URL: &str = "https://api.notion.com";
+static PAGE_SIZE: OnceLock<u8> = OnceLock::new();
 static NOTION_VERSION: &str = "2022-06-28";
+static MAX_PAGE_SIZE: u8 = 100;
+static MAX_REDIRECTS: usize = 5;
+/// Largest file [`download`] accepts.
+pub static MAX_DOWNLOAD_SIZE: usize = 20 * 1024 * 1024;
+static SYNC_LOCK: Mutex<()> = Mutex::const_new(());
 
 
 fn get_http_client() -> Client<HttpsConnector, Body> {
@@ -51,7 +70,7 @@ fn get_http_client() -> Client<HttpsConnector, Body> {
       Client::builder().build(
         HttpsConnectorBuilder::new()
         .with_native_roots()
-        .https_only()
+        .https_or_http()
         .enable_http1()
         .build()
       )
@@ -59,10 +78,70 @@ fn get_http_client() -> Client<HttpsConnector, Body> {
   ).clone()
 }
 
+/// Plain HTTP is only allowed to loopback hosts, such as a local stand-in
+/// for Notion.
+fn is_allowed_uri(uri: &Uri) -> bool {
+  match uri.scheme_str() {
+    Some("https") => true,
+    Some("http") => uri.host().is_some_and(
+      |host| {
+        host == "localhost"
+          || host
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .parse::<IpAddr>()
+            .is_ok_and(|address| address.is_loopback())
+      }
+    ),
+    _ => false
+  }
+}
+
+/// Notion API base url, read from `NOTION_API_BASE_URL`.
+fn get_api_base_url() -> &'static str {
+  API_BASE_URL.get_or_init(
+    || {
+      let base_url: String = env::var("NOTION_API_BASE_URL")
+        .unwrap_or(DEFAULT_API_BASE_URL.into())
+        .trim_end_matches('/')
+        .into();
+
+      let uri: Uri = base_url.parse().unwrap_or_else(
+        |error| panic!("NOTION_API_BASE_URL `{base_url}` is invalid: {error}")
+      );
+      if !is_allowed_uri(&uri) {
+        panic!("NOTION_API_BASE_URL `{base_url}` must use HTTPS unless it is a loopback address.");
+      }
+
+      base_url.into()
+    }
+  )
+}
+
+/// Page size sent with every database query, read from `NOTION_PAGE_SIZE`.
+/// Notion caps it at 100, which is also the default.
+fn get_page_size() -> u8 {
+  *PAGE_SIZE.get_or_init(
+    || {
+      env::var("NOTION_PAGE_SIZE")
+        .ok()
+        .and_then(|size| size.parse::<u8>().ok())
+        .unwrap_or(MAX_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE)
+    }
+  )
+}
+
 fn build_request(
-  url: &str
-) -> Result<Request<Body>> {
-  let builder: Builder = Request::post(url)
+  method: Method,
+  url: &str,
+  body: Option<&Value>
+) -> Result<Request<Body>, NotionError> {
+  let body: String = body.map(Value::to_string).unwrap_or_default();
+
+  let builder: Builder = Request::builder()
+    .method(method)
+    .uri(url)
     .header(
       header::USER_AGENT,
       "Rust@2021/hyper@0.14.26/hyper-rustls@0.24.0"
@@ -102,82 +181,872 @@ fn build_request(
     )
     .header(
       header::CONTENT_LENGTH,
-      0
+      body.len()
     );
 
-  let request: Request<Body> = builder.body(Body::empty())?;
+  let request: Request<Body> = builder.body(Body::from(body))?;
 
   debug!("Updated headers: {:?}", request.headers());
 
   Ok(request)
 }
 
-async fn request(url: &str) -> Result<Value> {
-  debug!("Sending request: {:?}", url);
+async fn decode_body(
+  response: Response<Body>
+) -> Result<String, NotionError> {
+  let gzipped: bool = response
+    .headers()
+    .get(header::CONTENT_ENCODING)
+    .is_some_and(|encoding| encoding.as_bytes() == b"gzip");
+
+  let bytes: Bytes = body::to_bytes(response).await?;
+
+  if !gzipped {
+    return String::from_utf8(bytes.to_vec()).map_err(
+      |error| NotionError::Decode(error.to_string())
+    );
+  }
+
+  let mut body: String = String::new();
+
+  GzDecoder::new(&*bytes)
+    .read_to_string(&mut body)
+    .map_err(
+      |error| NotionError::Decode(error.to_string())
+    )?;
+
+  Ok(body)
+}
+
+async fn send(
+  method: &Method,
+  url: &str,
+  body: Option<&Value>
+) -> Result<Value, NotionError> {
+  debug!("Sending request: {} {:?} {:?}", method, url, body);
 
   let response: Response<Body> = get_http_client().request(
-    build_request(url)?
+    build_request(method.clone(), url, body)?
   ).await?;
 
-  let mut body: String = String::new();
+  let status: StatusCode = response.status();
+
+  if status == StatusCode::TOO_MANY_REQUESTS {
+    return Err(
+      NotionError::RateLimited {
+        retry_after: response
+          .headers()
+          .get(header::RETRY_AFTER)
+          .and_then(|value| value.to_str().ok())
+          .and_then(|value| value.trim().parse::<u64>().ok())
+          .map(Duration::from_secs)
+      }
+    );
+  }
 
-  GzDecoder::new(
-    &*body::to_bytes(response).await?
-  ).read_to_string(&mut body)?;
+  let body: String = decode_body(response).await?;
 
   debug!("Decoded body: {:?}", body);
-  
-  Ok(serde_json::from_str(&body)?)
+
+  if !status.is_success() {
+    return Err(
+      NotionError::Status { status, body }
+    );
+  }
+
+  serde_json::from_str(&body).map_err(
+    |error| NotionError::Decode(error.to_string())
+  )
 }
 
-pub async fn update_all() {
+/// Send a request to Notion, retrying transient failures according to
+/// [`RetryPolicy::get`].
+async fn request(
+  method: Method,
+  url: &str,
+  body: Option<&Value>
+) -> Result<Value, NotionError> {
+  let policy: &RetryPolicy = RetryPolicy::get();
+  let mut attempt: u32 = 1;
+
+  loop {
+    let error: NotionError = match timeout(policy.timeout, send(&method, url, body)).await {
+      Ok(Ok(response)) => return Ok(response),
+      Ok(Err(error)) => error,
+      Err(_) => NotionError::Timeout(policy.timeout)
+    };
+
+    if !error.is_retryable() {
+      return Err(error);
+    }
+
+    if attempt >= policy.max_attempts {
+      return Err(
+        NotionError::RetriesExhausted {
+          attempts: attempt,
+          last: Box::new(error)
+        }
+      );
+    }
+
+    let delay: Duration = policy.delay(attempt, error.retry_after());
+
+    warn!(
+      "Request to {} failed (attempt {}/{}), retrying in {:?}: {}",
+      url, attempt, policy.max_attempts, delay, error
+    );
+
+    sleep(delay).await;
+    attempt += 1;
+  }
+}
+
+/// The live Notion API.
+pub struct NotionApi;
+
+impl DataSource for NotionApi {
+  fn query_database<'a>(
+    self: &'a Self,
+    data_type: &'a NotionDataType,
+    body: &'a Value,
+    filter_properties: &'a [&'a str]
+  ) -> SourceFuture<'a> {
+    Box::pin(
+      async move {
+        let url: String = format!(
+          "{base_url}/v1/databases/{database_id}/query{query}",
+          base_url = get_api_base_url(),
+          database_id = data_type.get_databse_id(),
+          query = filter_properties
+            .iter()
+            .enumerate()
+            .map(
+              |(index, property)| format!(
+                "{}filter_properties={property}",
+                if index == 0 { '?' } else { '&' }
+              )
+            )
+            .collect::<String>()
+        );
+
+        request(Method::POST, &url, Some(body)).await
+      }
+    )
+  }
+
+  fn block_children<'a>(
+    self: &'a Self,
+    block_id: &'a str,
+    page_size: u8,
+    start_cursor: Option<&'a str>
+  ) -> SourceFuture<'a> {
+    Box::pin(
+      async move {
+        let url: String = format!(
+          "{base_url}/v1/blocks/{block_id}/children?page_size={page_size}{cursor}",
+          base_url = get_api_base_url(),
+          cursor = start_cursor
+            .map(|cursor| format!("&start_cursor={cursor}"))
+            .unwrap_or_default()
+        );
+
+        request(Method::GET, &url, None).await
+      }
+    )
+  }
+
+  fn retrieve_database<'a>(
+    self: &'a Self,
+    data_type: &'a NotionDataType
+  ) -> SourceFuture<'a> {
+    Box::pin(
+      async move {
+        let url: String = format!(
+          "{base_url}/v1/databases/{database_id}",
+          base_url = get_api_base_url(),
+          database_id = data_type.get_databse_id()
+        );
+
+        request(Method::GET, &url, None).await
+      }
+    )
+  }
+
+  fn retrieve_page<'a>(
+    self: &'a Self,
+    page_id: &'a str
+  ) -> SourceFuture<'a> {
+    Box::pin(
+      async move {
+        let url: String = format!(
+          "{base_url}/v1/pages/{page_id}",
+          base_url = get_api_base_url()
+        );
+
+        request(Method::GET, &url, None).await
+      }
+    )
+  }
+}
+
+/// How much of each type a sync downloads, from the least to the most
+/// thorough.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SyncMode {
+  /// Only rows edited since the type's watermark, merged into the cached
+  /// records. Types without a watermark are fetched in full.
+  Incremental,
+  /// Incremental, then list every page id to drop deleted rows.
+  IncrementalWithScan,
+  /// Every row, replacing the cached records.
+  Full
+}
+
+/// Records of one type fetched from a database query.
+pub struct FetchedData {
+  pub records: Vec<NotionData>,
+  /// Pages that were returned but could not be parsed.
+  pub skipped: Vec<ParseDiagnostic>,
+  /// Pages whose content could not be fetched, with the error. Their cached
+  /// records should be kept.
+  pub failed: Vec<(String, String)>,
+  /// Newest `last_edited_time` among the returned pages.
+  pub last_edited_time: Option<DateTime<Utc>>
+}
+
+/// Apply re-fetched rows to the cached records of one type. Returns whether
+/// anything changed.
+fn merge(
+  records: &mut Vec<NotionData>,
+  fetched: FetchedData
+) -> bool {
+  let before: usize = records.len();
+  records.retain(|record| !fetched.skipped.iter().any(|diagnostic| diagnostic.page_id == record.id()));
+  let mut changed: bool = records.len() != before;
+
+  for record in fetched.records {
+    match records.iter_mut().find(|cached| cached.id() == record.id()) {
+      Some(cached) => {
+        let unchanged: bool = serde_json::to_value(relations::unresolved(cached)).ok()
+          == serde_json::to_value(&record).ok();
+        if !unchanged {
+          *cached = record;
+          changed = true;
+        }
+      },
+      None => {
+        records.push(record);
+        changed = true;
+      }
+    }
+  }
+
+  changed
+}
+
+/// Fetch the given types, each in its own mode, then resolve relations across the fetched records
+/// and the current snapshot's records of every other type, and publish the
+/// result as a new snapshot. A type that fails to fetch keeps its records,
+/// and nothing is published when no record changed. Fails, after publishing
+/// the types that did fetch, if any type failed.
+pub async fn update_types(
+  requests: &[(NotionDataType, SyncMode)]
+) -> Result<()> {
+  // Concurrent syncs would each publish a dataset missing the other's work.
+  let _guard: MutexGuard<()> = SYNC_LOCK.lock().await;
+
+  let current: Arc<Snapshot> = CacheStorage::get().snapshot();
+  let mut dataset: Dataset = HashMap::new();
+  let mut watermarks: Watermarks = current.watermarks().clone();
+  let mut changed: bool = false;
+  let mut failures: Vec<String> = Vec::new();
+  let mut synced: Vec<(NotionDataType, DateTime<Utc>)> = Vec::new();
+
   for data_type in NotionDataType::iterator() {
-    CacheStorage::get().update(
-      &data_type,
-      fetch_data(&data_type).await.unwrap()
-    ).await;
+    let Some(mode) = requests
+      .iter()
+      .find(|(requested, _)| *requested == data_type)
+      .map(|(_, mode)| *mode) else {
+      continue;
+    };
+
+    let since: Option<DateTime<Utc>> = match mode {
+      SyncMode::Full => None,
+      SyncMode::Incremental | SyncMode::IncrementalWithScan => current.watermark(&data_type)
+    };
+
+    let started_at: DateTime<Utc> = Utc::now();
+    let fetched: FetchedData = match fetch_data(&data_type, since.as_ref()).await {
+      Ok(fetched) => fetched,
+      Err(error) => {
+        error!(
+          "Update {:?} failed, keeping cached data: {:#}",
+          data_type, error
+        );
+        CacheStorage::get().mark_failed(&data_type, format!("{error:#}"));
+        failures.push(format!("{}: {:#}", data_type.name(), error));
+        continue;
+      }
+    };
+    let mut complete: bool = true;
+
+    // Pages that failed keep their cached records, so the next sync must
+    // fetch them again.
+    if fetched.failed.is_empty() {
+      if let Some(time) = fetched.last_edited_time.max(since) {
+        watermarks.insert(data_type.clone(), time);
+      }
+    } else {
+      let error: String = fetched.failed
+        .iter()
+        .map(|(page_id, error)| format!("page {page_id}: {error}"))
+        .collect::<Vec<String>>()
+        .join("; ");
+      error!(
+        "Update {:?} content failed, keeping cached pages: {}",
+        data_type, error
+      );
+      CacheStorage::get().mark_failed(&data_type, error.clone());
+      failures.push(format!("{}: {}", data_type.name(), error));
+      complete = false;
+    }
+
+    let skipped: Vec<ParseDiagnostic> = fetched.skipped.clone();
+    let fetched_ids: Vec<String> = fetched.records
+      .iter()
+      .map(|record| record.id().to_string())
+      .chain(skipped.iter().map(|diagnostic| diagnostic.page_id.clone()))
+      .collect();
+
+    let mut records: Vec<NotionData> = match since {
+      None => {
+        changed = true;
+        let mut records: Vec<NotionData> = fetched.records;
+        records.extend(
+          fetched.failed
+            .iter()
+            .filter_map(|(page_id, _)| current.request(page_id, &data_type))
+        );
+        records
+      },
+      Some(_) => {
+        let mut records: Vec<NotionData> = current.request_all(&data_type);
+        changed |= merge(&mut records, fetched);
+        records
+      }
+    };
+
+    if since.is_some() && mode == SyncMode::IncrementalWithScan {
+      match fetch_page_ids(&data_type).await {
+        Ok(ids) => {
+          let before: usize = records.len();
+          records.retain(|record| ids.contains(record.id()));
+          if records.len() != before {
+            debug!("Dropped {} deleted {:?} records.", before - records.len(), data_type);
+            changed = true;
+          }
+        },
+        Err(error) => {
+          error!(
+            "Scan {:?} ids failed, keeping deleted records: {:#}",
+            data_type, error
+          );
+          CacheStorage::get().mark_failed(&data_type, format!("{error:#}"));
+          failures.push(format!("{} scan: {:#}", data_type.name(), error));
+          complete = false;
+        }
+      }
+    }
+
+    match since {
+      None => SyncReport::get().record(&data_type, records.len(), skipped).await,
+      Some(_) => SyncReport::get().merge(&data_type, records.len(), &fetched_ids, skipped).await
+    }
+
+    if complete {
+      synced.push((data_type.clone(), started_at));
+    }
+    dataset.insert(data_type, records);
     sleep(Duration::from_millis(500)).await;
   }
+
+  for (data_type, started_at) in synced {
+    CacheStorage::get().mark_synced(&data_type, started_at);
+  }
+
+  if changed {
+    publish(&current, dataset, watermarks).await;
+  } else {
+    debug!("No changes since snapshot generation {}.", current.generation);
+  }
+
+  if !failures.is_empty() {
+    return Err(anyhow!("Update failed for {}", failures.join("; ")));
+  }
+
+  Ok(())
 }
 
-pub async fn fetch_data(
+/// Fill the types missing from `dataset` from `current`, resolve relations
+/// and publish the result.
+async fn publish(
+  current: &Snapshot,
+  mut dataset: Dataset,
+  watermarks: Watermarks
+) {
+  for data_type in NotionDataType::iterator() {
+    if let Entry::Vacant(entry) = dataset.entry(data_type) {
+      let cached: Vec<NotionData> = current.request_all(entry.key());
+      entry.insert(cached);
+    }
+  }
+
+  relations::resolve(&mut dataset);
+
+  let generation: u64 = CacheStorage::get().publish(dataset, watermarks);
+
+  info!("Published snapshot generation {}.", generation);
+
+  if let Err(error) = persist::save(&CacheStorage::get().snapshot(), &CacheStorage::get().synced_at()).await {
+    error!("Save snapshot generation {} failed: {:#}", generation, error);
+  }
+}
+
+/// Compare the schema mapping with every database and list the mismatches.
+pub async fn validate_schema() -> Result<Vec<String>> {
+  let mut problems: Vec<String> = Vec::new();
+
+  for data_type in NotionDataType::iterator() {
+    let database: Value = source::get().retrieve_database(&data_type).await?;
+
+    problems.extend(
+      Schema::get().validate(&data_type, &database)
+    );
+  }
+
+  Ok(problems)
+}
+
+/// Parse a page of the database of a type with the matching `from_json`.
+fn parse_page(
   data_type: &NotionDataType,
-) -> Result<Vec<NotionData>> {
-  let url: Arc<str> = format!(
-    "https://api.notion.com/v1/databases/{database_id}/query",
-    database_id = data_type.get_databse_id()
-  ).into();
+  json_data: &Value
+) -> Result<NotionData> {
+  match data_type {
+    NotionDataType::Member => Member::from_json(json_data).map(NotionData::Member),
+    NotionDataType::Group => Group::from_json(json_data).map(NotionData::Group),
+    NotionDataType::Club => Club::from_json(json_data).map(NotionData::Club),
+    NotionDataType::Event => Event::from_json(json_data).map(NotionData::Event),
+    NotionDataType::Article => Article::from_json(json_data).map(NotionData::Article),
+    NotionDataType::Sponsor => Sponsor::from_json(json_data).map(NotionData::Sponsor)
+  }
+}
 
-  let response: Value = request(&url).await?;
+/// Fetch what is not in the page properties: the blocks of an article.
+async fn fetch_content(record: &mut NotionData) -> Result<()> {
+  if let NotionData::Article(article) = record {
+    article.content = ArticleContent::Blocks(
+      fetch_blocks(&article.id).await.map_err(
+        |error| anyhow!("Fetch content failed: {error:#}")
+      )?
+    );
+  }
 
+  Ok(())
+}
+
+/// `id` as Notion returns page ids, a lowercase UUID with dashes, if it is
+/// a page id with or without dashes, such as the one in a page url. Ids
+/// from requests and webhook events are checked before they become part of
+/// a Notion url or fixture path, and compared with cached ids.
+pub fn normalize_page_id(id: &str) -> Option<String> {
+  let hex: String = match id.len() {
+    32 => id.into(),
+    36 => {
+      let parts: Vec<&str> = id.split('-').collect();
+      if !parts.iter().map(|part| part.len()).eq([8, 4, 4, 4, 12]) {
+        return None;
+      }
+      parts.concat()
+    },
+    _ => return None
+  };
+  if !hex.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+    return None;
+  }
+
+  let hex: String = hex.to_ascii_lowercase();
+
+  Some(format!("{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..]))
+}
+
+/// Fetch one page of the database of a type. `None` when the page was
+/// deleted, moved out of the database or cannot be parsed, in which case it
+/// should no longer be served.
+pub async fn fetch_page(
+  data_type: &NotionDataType,
+  page_id: &str
+) -> Result<Option<NotionData>> {
+  let page_id: String = normalize_page_id(page_id).ok_or(
+    anyhow!("`{page_id}` is not a page id.")
+  )?;
+
+  let json_data: Value = match source::get().retrieve_page(&page_id).await {
+    Ok(json_data) => json_data,
+    Err(NotionError::Status { status: StatusCode::NOT_FOUND, .. }) => {
+      debug!("{:?} page {} does not exist.", data_type, page_id);
+      return Ok(None);
+    },
+    Err(error) => return Err(error.into())
+  };
+
+  if json_data["archived"].as_bool().unwrap_or(false)
+    || json_data["in_trash"].as_bool().unwrap_or(false) {
+    debug!("{:?} page {} is archived.", data_type, page_id);
+    return Ok(None);
+  }
+
+  let parent: Option<NotionDataType> = json_data["parent"]["database_id"]
+    .as_str()
+    .and_then(NotionDataType::from_database_id);
+  if parent.as_ref() != Some(data_type) {
+    debug!("{:?} page {} is not in the {:?} database.", data_type, page_id, data_type);
+    return Ok(None);
+  }
+
+  let mut record: NotionData = match parse_page(data_type, &json_data) {
+    Ok(record) => record,
+    Err(error) => {
+      warn!("Skip {:?} page {}: {:#}", data_type, page_id, error);
+      return Ok(None);
+    }
+  };
+  fetch_content(&mut record).await?;
+
+  let mut data: Vec<NotionData> = vec![record];
+  MediaStore::get().mirror_all(&mut data).await;
+
+  Ok(data.pop())
+}
+
+/// Fetch one page and publish a snapshot with it updated, added or, if
+/// [`fetch_page`] found nothing to serve, removed.
+pub async fn update_page(
+  data_type: &NotionDataType,
+  page_id: &str
+) -> Result<()> {
+  // Cached ids are as Notion returns them.
+  let page_id: String = normalize_page_id(page_id).ok_or(
+    anyhow!("`{page_id}` is not a page id.")
+  )?;
+
+  let _guard: MutexGuard<()> = SYNC_LOCK.lock().await;
+
+  let record: Option<NotionData> = fetch_page(data_type, &page_id).await?;
+
+  let Some(generation) = CacheStorage::get().upsert(data_type, &page_id, record) else {
+    debug!("{:?} page {} is unchanged.", data_type, page_id);
+    return Ok(());
+  };
+
+  info!("Published snapshot generation {} for {:?} page {}.", generation, data_type, page_id);
+
+  if let Err(error) = persist::save(&CacheStorage::get().snapshot(), &CacheStorage::get().synced_at()).await {
+    error!("Save snapshot generation {} failed: {:#}", generation, error);
+  }
+
+  Ok(())
+}
+
+/// Fetch the rows of a type, or only those edited on or after `since`.
+pub async fn fetch_data(
+  data_type: &NotionDataType,
+  since: Option<&DateTime<Utc>>
+) -> Result<FetchedData> {
   let mut data: Vec<NotionData> = Vec::new();
+  let mut skipped: Vec<ParseDiagnostic> = Vec::new();
+  let mut failed: Vec<(String, String)> = Vec::new();
+  let mut last_edited_time: Option<DateTime<Utc>> = None;
+  let mut start_cursor: Option<String> = None;
+
+  loop {
+    let mut body: Value = json!(
+      {"page_size": get_page_size()}
+    );
+    if let Some(since) = since {
+      body["filter"] = json!(
+        {
+          "timestamp": "last_edited_time",
+          "last_edited_time": {
+            "on_or_after": since.to_rfc3339_opts(SecondsFormat::Millis, true)
+          }
+        }
+      );
+    }
+    if let Some(cursor) = &start_cursor {
+      body["start_cursor"] = cursor.as_str().into();
+    }
+
+    let response: Value = source::get().query_database(data_type, &body, &[]).await?;
+
+    for json_data in response["results"].as_array().ok_or(
+      anyhow!("Parse JSON failed.")
+    )?.iter() {
+      let edited: Option<DateTime<Utc>> = json_data["last_edited_time"]
+        .as_str()
+        .and_then(|time| DateTime::parse_from_rfc3339(time).ok())
+        .map(|time| time.with_timezone(&Utc));
+      last_edited_time = last_edited_time.max(edited);
 
-  for json_data in response["results"].as_array().ok_or(
-    anyhow!("Parse JSON failed.")
-  )?.into_iter() {
-    data.push(
-      match data_type {
-        NotionDataType::Member => NotionData::Member(
-          Member::from_json(json_data).await.unwrap_or(Member::default())
-        ),
-        NotionDataType::Group => NotionData::Group(
-          Group::from_json(json_data).await.unwrap_or(Group::default())
-        ),
-        NotionDataType::Club => NotionData::Club(
-          Club::from_json(json_data).await.unwrap_or(Club::default())
-        ),
-        NotionDataType::Event => NotionData::Event(
-          Event::from_json(json_data).await.unwrap_or(Event::default())
-        ),
-        NotionDataType::Article => NotionData::Article(
-          Article::from_json(json_data).await.unwrap_or(Article::default())
-        ),
-        NotionDataType::Sponsor => NotionData::Sponsor(
-          Sponsor::from_json(json_data).await.unwrap_or(Sponsor::default())
-        ),
+      // A page whose content cannot be fetched is left out without failing
+      // the whole type, but unlike one that cannot be parsed, it is kept as
+      // cached.
+      match parse_page(data_type, json_data) {
+        Ok(mut record) => match fetch_content(&mut record).await {
+          Ok(()) => data.push(record),
+          Err(error) => {
+            warn!(
+              "Keep cached {:?} page {}: {:#}",
+              data_type, record.id(), error
+            );
+            failed.push((record.id().into(), format!("{error:#}")));
+          }
+        },
+        Err(error) => {
+          let diagnostic: ParseDiagnostic = ParseDiagnostic::new(json_data, &error);
+          warn!(
+            "Skip {:?} page {}: {:#}",
+            data_type, diagnostic.page_id, error
+          );
+          skipped.push(diagnostic);
+        }
       }
-    )
+    }
+
+    if !response["has_more"].as_bool().unwrap_or(false) {
+      break;
+    }
+
+    start_cursor = Some(
+      response["next_cursor"]
+        .as_str()
+        .ok_or(
+          anyhow!("Get `next_cursor` failed.")
+        )?
+        .into()
+    );
+  }
+
+  debug!(
+    "Fetched {} {:?} records, skipped {}, failed {}.",
+    data.len(), data_type, skipped.len(), failed.len()
+  );
+
+  MediaStore::get().mirror_all(&mut data).await;
+
+  Ok(
+    FetchedData {
+      records: data,
+      skipped,
+      failed,
+      last_edited_time
+    }
+  )
+}
+
+/// Id of every page in the database of a type, fetching as little of each
+/// page as Notion allows.
+pub async fn fetch_page_ids(
+  data_type: &NotionDataType
+) -> Result<HashSet<String>> {
+  let mut ids: HashSet<String> = HashSet::new();
+  let mut start_cursor: Option<String> = None;
+
+  loop {
+    let mut body: Value = json!(
+      {"page_size": get_page_size()}
+    );
+    if let Some(cursor) = &start_cursor {
+      body["start_cursor"] = cursor.as_str().into();
+    }
+
+    // The title property always has the id `title`.
+    let response: Value = source::get().query_database(data_type, &body, &["title"]).await?;
+
+    for json_data in response["results"].as_array().ok_or(
+      anyhow!("Parse JSON failed.")
+    )?.iter() {
+      ids.insert(
+        json_data["id"]
+          .as_str()
+          .ok_or(
+            anyhow!("Get `id` failed.")
+          )?
+          .into()
+      );
+    }
+
+    if !response["has_more"].as_bool().unwrap_or(false) {
+      break;
+    }
+
+    start_cursor = Some(
+      response["next_cursor"]
+        .as_str()
+        .ok_or(
+          anyhow!("Get `next_cursor` failed.")
+        )?
+        .into()
+    );
+  }
+
+  Ok(ids)
+}
+
+/// Read a whole body, failing as soon as it grows past `limit` bytes.
+async fn read_limited(
+  mut body: Body,
+  limit: usize
+) -> Result<Bytes, NotionError> {
+  if body.size_hint().lower() > limit as u64 {
+    return Err(NotionError::TooLarge(limit));
+  }
+
+  let mut bytes: Vec<u8> = Vec::new();
+
+  while let Some(chunk) = body.data().await {
+    let chunk: Bytes = chunk?;
+    if bytes.len() + chunk.len() > limit {
+      return Err(NotionError::TooLarge(limit));
+    }
+    bytes.extend_from_slice(&chunk);
   }
 
-  Ok(data)
+  Ok(bytes.into())
+}
+
+/// Download a file hosted outside the Notion API, following redirects.
+/// Unlike [`request`], no Notion credentials are sent.
+pub async fn download(url: &str) -> Result<(String, Bytes), NotionError> {
+  let mut uri: Uri = url.parse().map_err(hyper::http::Error::from)?;
+
+  for _ in 0..MAX_REDIRECTS {
+    if !is_allowed_uri(&uri) {
+      return Err(NotionError::Insecure(uri.to_string()));
+    }
+
+    let request: Request<Body> = Request::get(uri.clone())
+      .header(
+        header::USER_AGENT,
+        "Rust@2021/hyper@0.14.26/hyper-rustls@0.24.0"
+      )
+      .body(Body::empty())?;
+
+    let policy: &RetryPolicy = RetryPolicy::get();
+    let response: Response<Body> = timeout(policy.timeout, get_http_client().request(request))
+      .await
+      .map_err(|_| NotionError::Timeout(policy.timeout))??;
+    let status: StatusCode = response.status();
+
+    if status.is_redirection() {
+      let location: &str = response
+        .headers()
+        .get(header::LOCATION)
+        .and_then(|location| location.to_str().ok())
+        .ok_or(
+          NotionError::Decode(format!("Redirect from {uri} without a location."))
+        )?;
+
+      uri = match location.starts_with('/') {
+        true => format!(
+          "{}://{}{location}",
+          uri.scheme_str().unwrap_or("https"),
+          uri.authority().map(|authority| authority.as_str()).unwrap_or("")
+        ).parse(),
+        false => location.parse()
+      }.map_err(hyper::http::Error::from)?;
+
+      continue;
+    }
+
+    if !status.is_success() {
+      return Err(
+        NotionError::Status {
+          status,
+          body: String::new()
+        }
+      );
+    }
+
+    let content_type: String = response
+      .headers()
+      .get(header::CONTENT_TYPE)
+      .and_then(|content_type| content_type.to_str().ok())
+      .unwrap_or("application/octet-stream")
+      .into();
+
+    let bytes: Bytes = timeout(policy.timeout, read_limited(response.into_body(), MAX_DOWNLOAD_SIZE))
+      .await
+      .map_err(|_| NotionError::Timeout(policy.timeout))??;
+
+    return Ok((content_type, bytes));
+  }
+
+  Err(
+    NotionError::Decode(format!("Too many redirects downloading {url}."))
+  )
+}
+
+/// Fetch every child block of a page or block, following pagination and
+/// descending into nested blocks.
+pub fn fetch_blocks(
+  block_id: &str
+) -> Pin<Box<dyn Future<Output = Result<Vec<Block>>> + Send + '_>> {
+  Box::pin(
+    async move {
+      let mut blocks: Vec<Block> = Vec::new();
+      let mut start_cursor: Option<String> = None;
+
+      loop {
+        let response: Value = source::get().block_children(
+          block_id,
+          get_page_size(),
+          start_cursor.as_deref()
+        ).await?;
+
+        for json_data in response["results"].as_array().ok_or(
+          anyhow!("Parse JSON failed.")
+        )?.iter() {
+          let children: Vec<Block> = if Block::should_fetch_children(json_data) {
+            fetch_blocks(
+              json_data["id"]
+                .as_str()
+                .ok_or(
+                  anyhow!("Get `id` failed.")
+                )?
+            ).await?
+          } else {
+            Vec::new()
+          };
+
+          blocks.push(Block::from_json(json_data, children)?);
+        }
+
+        if !response["has_more"].as_bool().unwrap_or(false) {
+          break;
+        }
+
+        start_cursor = Some(
+          response["next_cursor"]
+            .as_str()
+            .ok_or(
+              anyhow!("Get `next_cursor` failed.")
+            )?
+            .into()
+        );
+      }
+
+      Ok(blocks)
+    }
+  )
 }
@@ -0,0 +1,88 @@
+use std::time::Duration;
+
+use hyper::StatusCode;
+use thiserror::Error;
+
+
+#[derive(Debug, Error)]
+pub enum NotionError {
+  #[error("Build request failed: {0}")]
+  Request(#[from] hyper::http::Error),
+  #[error("Send request failed: {0}")]
+  Transport(#[from] hyper::Error),
+  #[error("Request timed out after {0:?}.")]
+  Timeout(Duration),
+  #[error("Rate limited by Notion (retry after {retry_after:?}).")]
+  RateLimited {
+    retry_after: Option<Duration>
+  },
+  #[error("Notion responded with {status}: {body}")]
+  Status {
+    status: StatusCode,
+    body: String
+  },
+  #[error("Refusing to request {0} over plain HTTP.")]
+  Insecure(String),
+  #[error("Response is larger than {0} bytes.")]
+  TooLarge(usize),
+  #[error("Decode response failed: {0}")]
+  Decode(String),
+  #[error("Gave up after {attempts} attempts: {last}")]
+  RetriesExhausted {
+    attempts: u32,
+    last: Box<NotionError>
+  }
+}
+
+impl NotionError {
+  /// Whether sending the same request again may succeed.
+  ///
+  /// Rate limits, server-side failures, timeouts and conflicts are
+  /// transient; anything else (bad request, unauthorized, not found...) will
+  /// fail the same way on every attempt.
+  pub fn is_retryable(self: &Self) -> bool {
+    match self {
+      NotionError::Transport(_)
+        | NotionError::Timeout(_)
+        | NotionError::RateLimited { .. } => true,
+      NotionError::Status { status, .. } => matches!(
+        *status,
+        StatusCode::CONFLICT
+          | StatusCode::REQUEST_TIMEOUT
+          | StatusCode::INTERNAL_SERVER_ERROR
+          | StatusCode::BAD_GATEWAY
+          | StatusCode::SERVICE_UNAVAILABLE
+          | StatusCode::GATEWAY_TIMEOUT
+      ),
+      _ => false
+    }
+  }
+
+  pub fn retry_after(self: &Self) -> Option<Duration> {
+    match self {
+      NotionError::RateLimited { retry_after } => *retry_after,
+      _ => None
+    }
+  }
+}
+
+
+/// A page property that is missing or has an unexpected shape.
+#[derive(Debug, Error)]
+#[error("Get `{path}` failed: {reason}.")]
+pub struct FieldError {
+  pub path: String,
+  pub reason: String
+}
+
+impl FieldError {
+  pub fn new(
+    path: impl Into<String>,
+    reason: impl Into<String>
+  ) -> FieldError {
+    FieldError {
+      path: path.into(),
+      reason: reason.into()
+    }
+  }
+}
@@ -0,0 +1,94 @@
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+use serde_json::Value;
+use anyhow::Result;
+
+use super::error::FieldError;
+
+
+/// A file or image referenced by a page. Files uploaded to Notion are served
+/// from signed urls that stop working after `expiry_time`; external files
+/// never expire.
+///
+/// Serialized as its url only, so a deserialized file has no known expiry.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NotionFile {
+  pub url: String,
+  pub expiry_time: Option<DateTime<Utc>>
+}
+
+impl Serialize for NotionFile {
+  fn serialize<S: Serializer>(
+    self: &Self,
+    serializer: S
+  ) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&self.url)
+  }
+}
+
+impl<'de> Deserialize<'de> for NotionFile {
+  fn deserialize<D: Deserializer<'de>>(
+    deserializer: D
+  ) -> Result<NotionFile, D::Error> {
+    Ok(
+      NotionFile {
+        url: String::deserialize(deserializer)?,
+        expiry_time: None
+      }
+    )
+  }
+}
+
+impl NotionFile {
+  /// Parse a Notion file object (an entry of a files property, a page cover
+  /// or icon, or an image block). `path` locates it for diagnostics.
+  ///
+  /// A missing file, such as an empty files property or a page without an
+  /// icon, and an emoji icon are empty files with no url.
+  pub fn from_json(
+    json_data: &Value,
+    path: &str
+  ) -> Result<NotionFile> {
+    if json_data.is_null() {
+      return Ok(NotionFile::default());
+    }
+
+    match json_data["type"].as_str() {
+      Some("emoji" | "custom_emoji") => Ok(NotionFile::default()),
+      Some("external") => Ok(
+        NotionFile {
+          url: json_data["external"]["url"]
+            .as_str()
+            .ok_or(
+              FieldError::new(format!("{path}.external.url"), "expected a string")
+            )?
+            .into(),
+          expiry_time: None
+        }
+      ),
+      Some("file") => Ok(
+        NotionFile {
+          url: json_data["file"]["url"]
+            .as_str()
+            .ok_or(
+              FieldError::new(format!("{path}.file.url"), "expected a string")
+            )?
+            .into(),
+          expiry_time: json_data["file"]["expiry_time"]
+            .as_str()
+            .and_then(|time| DateTime::parse_from_rfc3339(time).ok())
+            .map(|time| time.with_timezone(&Utc))
+        }
+      ),
+      Some(other) => Err(
+        FieldError::new(
+          format!("{path}.type"),
+          format!("expected `file` or `external`, found `{other}`")
+        ).into()
+      ),
+      None => Err(
+        FieldError::new(path, "expected a file").into()
+      )
+    }
+  }
+}
@@ -0,0 +1,72 @@
+use std::{
+  collections::{BTreeMap, BTreeSet, HashMap},
+  ops::Bound
+};
+
+use super::types::NotionData;
+
+
+/// A condition on one indexed field of a record.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+  /// The field has this value, or this among its values.
+  Is(&'static str, String),
+  /// The field is at least this value.
+  AtLeast(&'static str, String),
+  /// The field is at most this value.
+  AtMost(&'static str, String)
+}
+
+/// Ids of the records of one type by the values of their indexed fields, so
+/// a filter is answered without looking at every record.
+#[derive(Debug, Default)]
+pub struct Index {
+  fields: HashMap<&'static str, BTreeMap<String, BTreeSet<String>>>
+}
+
+impl Index {
+  pub fn new<'a>(records: impl Iterator<Item = &'a NotionData>) -> Index {
+    let mut fields: HashMap<&'static str, BTreeMap<String, BTreeSet<String>>> = HashMap::new();
+
+    for record in records {
+      for (field, value) in record.index_values() {
+        fields
+          .entry(field)
+          .or_default()
+          .entry(value)
+          .or_default()
+          .insert(record.id().into());
+      }
+    }
+
+    Index { fields }
+  }
+
+  /// Ids of the records matching `filter`.
+  pub fn lookup(self: &Self, filter: &Filter) -> BTreeSet<String> {
+    let (field, range): (&str, (Bound<&str>, Bound<&str>)) = match filter {
+      Filter::Is(field, value) => (field, (Bound::Included(value), Bound::Included(value))),
+      Filter::AtLeast(field, value) => (field, (Bound::Included(value), Bound::Unbounded)),
+      Filter::AtMost(field, value) => (field, (Bound::Unbounded, Bound::Included(value)))
+    };
+
+    self.fields
+      .get(field)
+      .map(
+        |values| values
+          .range::<str, _>(range)
+          .flat_map(|(_, ids)| ids.iter().cloned())
+          .collect()
+      )
+      .unwrap_or_default()
+  }
+
+  /// Ids of the records matching every filter, `None` if there are no
+  /// filters.
+  pub fn matching(self: &Self, filters: &[Filter]) -> Option<BTreeSet<String>> {
+    filters
+      .iter()
+      .map(|filter| self.lookup(filter))
+      .reduce(|matching, ids| matching.intersection(&ids).cloned().collect())
+  }
+}
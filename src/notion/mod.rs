@@ -1,3 +1,16 @@
 pub mod types;
 pub mod client;
 pub mod cache;
+pub mod blocks;
+pub mod file;
+pub mod rich_text;
+pub mod render;
+pub mod error;
+pub mod retry;
+pub mod report;
+pub mod schema;
+pub mod relations;
+pub mod persist;
+pub mod source;
+pub mod webhook;
+pub mod index;
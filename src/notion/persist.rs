@@ -0,0 +1,148 @@
+use std::{
+  collections::HashMap,
+  env,
+  path::PathBuf,
+  sync::OnceLock
+};
+
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+use anyhow::{Result, anyhow};
+use tokio::fs;
+
+use super::{
+  cache::{ChangeTimes, Snapshot, SyncTimes, Watermarks},
+  relations::{self, Dataset},
+  types::{
+    NotionDataType,
+    NotionData,
+    Member,
+    Group,
+    Club,
+    Event,
+    Article,
+    Sponsor
+  }
+};
+
+
+static SNAPSHOT_PATH: OnceLock<PathBuf> = OnceLock::new();
+/// Bump whenever the stored shape of a record changes; snapshots with any
+/// other version are ignored.
+static SNAPSHOT_VERSION: u32 = 2;
+
+
+#[derive(Serialize)]
+struct SnapshotFile<'a> {
+  version: u32,
+  generation: u64,
+  watermarks: &'a Watermarks,
+  synced_at: &'a SyncTimes,
+  changed_at: ChangeTimes,
+  data: HashMap<&'a NotionDataType, Vec<&'a NotionData>>
+}
+
+#[derive(Deserialize)]
+struct StoredSnapshotFile {
+  version: u32,
+  generation: u64,
+  #[serde(default)]
+  watermarks: Watermarks,
+  #[serde(default)]
+  synced_at: SyncTimes,
+  #[serde(default)]
+  changed_at: ChangeTimes,
+  data: HashMap<NotionDataType, Vec<Value>>
+}
+
+
+/// Where the latest snapshot is kept, read from `SNAPSHOT_PATH`.
+fn get_snapshot_path() -> &'static PathBuf {
+  SNAPSHOT_PATH.get_or_init(
+    || {
+      env::var("SNAPSHOT_PATH")
+        .unwrap_or("snapshot.json".into())
+        .into()
+    }
+  )
+}
+
+fn parse_record(
+  data_type: &NotionDataType,
+  json_data: Value
+) -> Result<NotionData> {
+  Ok(
+    match data_type {
+      NotionDataType::Member => NotionData::Member(serde_json::from_value::<Member>(json_data)?),
+      NotionDataType::Group => NotionData::Group(serde_json::from_value::<Group>(json_data)?),
+      NotionDataType::Club => NotionData::Club(serde_json::from_value::<Club>(json_data)?),
+      NotionDataType::Event => NotionData::Event(serde_json::from_value::<Event>(json_data)?),
+      NotionDataType::Article => NotionData::Article(serde_json::from_value::<Article>(json_data)?),
+      NotionDataType::Sponsor => NotionData::Sponsor(serde_json::from_value::<Sponsor>(json_data)?)
+    }
+  )
+}
+
+/// Write `snapshot` and when its types were fetched to disk, replacing the
+/// previous one only once it is completely written.
+pub async fn save(
+  snapshot: &Snapshot,
+  synced_at: &SyncTimes
+) -> Result<()> {
+  let file: SnapshotFile = SnapshotFile {
+    version: SNAPSHOT_VERSION,
+    generation: snapshot.generation,
+    watermarks: snapshot.watermarks(),
+    synced_at,
+    changed_at: snapshot.changed_at(),
+    data: snapshot.records().collect()
+  };
+
+  let path: &PathBuf = get_snapshot_path();
+  let temporary_path: PathBuf = path.with_extension("json.tmp");
+
+  if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+    fs::create_dir_all(parent).await?;
+  }
+  fs::write(&temporary_path, serde_json::to_vec(&file)?).await?;
+  fs::rename(&temporary_path, path).await?;
+
+  Ok(())
+}
+
+/// Read the latest snapshot, returning its generation, records, watermarks,
+/// fetch times and change times. `None` when no snapshot has been written
+/// yet.
+pub async fn load() -> Result<Option<(u64, Dataset, Watermarks, SyncTimes, ChangeTimes)>> {
+  let bytes: Vec<u8> = match fs::read(get_snapshot_path()).await {
+    Ok(bytes) => bytes,
+    Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+    Err(error) => return Err(error.into())
+  };
+
+  let file: StoredSnapshotFile = serde_json::from_slice(&bytes)?;
+
+  if file.version != SNAPSHOT_VERSION {
+    return Err(
+      anyhow!(
+        "Snapshot version {} is not supported (expected {}).",
+        file.version, SNAPSHOT_VERSION
+      )
+    );
+  }
+
+  let mut dataset: Dataset = HashMap::new();
+
+  for (data_type, records) in file.data {
+    let records: Vec<NotionData> = records
+      .into_iter()
+      .map(|json_data| parse_record(&data_type, json_data))
+      .collect::<Result<_>>()?;
+
+    dataset.insert(data_type, records);
+  }
+
+  relations::restore_ids(&mut dataset);
+
+  Ok(Some((file.generation, dataset, file.watermarks, file.synced_at, file.changed_at)))
+}
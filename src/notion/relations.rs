@@ -0,0 +1,171 @@
+use std::collections::HashMap;
+
+use tracing::log::debug;
+
+use super::types::{NotionDataType, NotionData, Member, Group, Club};
+
+
+pub type Dataset = HashMap<NotionDataType, Vec<NotionData>>;
+
+
+fn lookup<'a, T>(
+  records: &'a HashMap<&str, T>,
+  ids: &'a [String],
+  owner: &'a str
+) -> impl Iterator<Item = &'a T> + 'a {
+  ids.iter().filter_map(
+    move |id| {
+      let record: Option<&T> = records.get(id.as_str());
+      if record.is_none() {
+        debug!("{} relates to unknown page {}.", owner, id);
+      }
+      record
+    }
+  )
+}
+
+/// Link related records using only the records of `dataset`.
+///
+/// Embedded records are one level deep: a member's groups carry no members,
+/// a group's members carry no groups, and an event's principals carry their
+/// groups and club. Relations to pages missing from the dataset are dropped.
+pub fn resolve(dataset: &mut Dataset) {
+  let records = |dataset: &Dataset, data_type: &NotionDataType| -> Vec<NotionData> {
+    dataset.get(data_type).cloned().unwrap_or_default()
+  };
+
+  let clubs: HashMap<String, Club> = records(dataset, &NotionDataType::Club)
+    .into_iter()
+    .filter_map(
+      |data| match data {
+        NotionData::Club(club) => Some((club.id.clone(), club)),
+        _ => None
+      }
+    )
+    .collect();
+
+  let flat_groups: HashMap<String, Group> = records(dataset, &NotionDataType::Group)
+    .into_iter()
+    .filter_map(
+      |data| match data {
+        NotionData::Group(mut group) => {
+          group.members = None;
+          Some((group.id.clone(), group))
+        },
+        _ => None
+      }
+    )
+    .collect();
+  let flat_groups: HashMap<&str, &Group> = flat_groups
+    .iter()
+    .map(|(id, group)| (id.as_str(), group))
+    .collect();
+
+  // Members with their club, and with or without their groups.
+  let mut flat_members: HashMap<String, Member> = HashMap::new();
+  let mut members: HashMap<String, Member> = HashMap::new();
+
+  for data in records(dataset, &NotionDataType::Member) {
+    let NotionData::Member(mut member) = data else { continue };
+
+    member.club = member.club_id
+      .as_ref()
+      .and_then(|id| clubs.get(id))
+      .cloned();
+    member.groups = None;
+    flat_members.insert(member.id.clone(), member.clone());
+
+    member.groups = Some(
+      lookup(&flat_groups, &member.group_ids, &member.id)
+        .map(|group| (*group).clone())
+        .collect()
+    );
+    members.insert(member.id.clone(), member);
+  }
+
+  let flat_members: HashMap<&str, &Member> = flat_members
+    .iter()
+    .map(|(id, member)| (id.as_str(), member))
+    .collect();
+  let full_members: HashMap<&str, &Member> = members
+    .iter()
+    .map(|(id, member)| (id.as_str(), member))
+    .collect();
+
+  for (data_type, data) in dataset.iter_mut() {
+    for record in data.iter_mut() {
+      match record {
+        NotionData::Member(member) => {
+          if let Some(resolved) = full_members.get(member.id.as_str()) {
+            *member = (*resolved).clone();
+          }
+        },
+        NotionData::Group(group) => {
+          group.members = Some(
+            lookup(&flat_members, &group.member_ids, &group.id)
+              .map(|member| (*member).clone())
+              .collect()
+          );
+        },
+        NotionData::Event(event) => {
+          event.principal = lookup(&full_members, &event.principal_ids, &event.id)
+            .map(|member| (*member).clone())
+            .collect();
+        },
+        _ => {}
+      }
+    }
+
+    debug!("Resolved relations of {} {:?} records.", data.len(), data_type);
+  }
+}
+
+/// Recover the relation ids of records read back from a snapshot file, which
+/// only stores the embedded records. Relations to pages that were missing
+/// when the snapshot was taken are lost until the next full sync.
+pub fn restore_ids(dataset: &mut Dataset) {
+  for record in dataset.values_mut().flatten() {
+    match record {
+      NotionData::Member(member) => {
+        member.group_ids = member.groups
+          .iter()
+          .flatten()
+          .map(|group| group.id.clone())
+          .collect();
+        member.club_id = member.club.as_ref().map(|club| club.id.clone());
+      },
+      NotionData::Group(group) => {
+        group.member_ids = group.members
+          .iter()
+          .flatten()
+          .map(|member| member.id.clone())
+          .collect();
+      },
+      NotionData::Event(event) => {
+        event.principal_ids = event.principal
+          .iter()
+          .map(|member| member.id.clone())
+          .collect();
+      },
+      _ => {}
+    }
+  }
+}
+
+/// A copy of `record` without its embedded records, as it is right after
+/// parsing, for telling whether a re-fetched row actually changed.
+pub fn unresolved(record: &NotionData) -> NotionData {
+  let mut record: NotionData = record.clone();
+
+  match &mut record {
+    NotionData::Member(member) => {
+      member.groups = None;
+      member.club = None;
+    },
+    NotionData::Group(group) => group.members = None,
+    NotionData::Event(event) => event.principal = Vec::new(),
+    _ => {}
+  }
+
+  record
+}
@@ -0,0 +1,480 @@
+use serde::Deserialize;
+
+use super::{
+  blocks::{Block, BlockKind},
+  rich_text::{RichText, RichTextSpan, SpanKind, plain_text}
+};
+
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all(deserialize = "snake_case"))]
+pub enum ContentFormat {
+  #[default]
+  Blocks,
+  Html,
+  Markdown
+}
+
+
+fn escape_html(text: &str) -> String {
+  let mut escaped: String = String::with_capacity(text.len());
+
+  for character in text.chars() {
+    match character {
+      '&' => escaped.push_str("&amp;"),
+      '<' => escaped.push_str("&lt;"),
+      '>' => escaped.push_str("&gt;"),
+      '"' => escaped.push_str("&quot;"),
+      '\'' => escaped.push_str("&#39;"),
+      _ => escaped.push(character)
+    }
+  }
+
+  escaped
+}
+
+fn escape_markdown(text: &str) -> String {
+  let mut escaped: String = String::with_capacity(text.len());
+
+  for character in text.chars() {
+    if matches!(
+      character,
+      '\\' | '`' | '*' | '_' | '~' | '[' | ']' | '<' | '>' | '|' | '$'
+    ) {
+      escaped.push('\\');
+    }
+    escaped.push(character);
+  }
+
+  escaped
+}
+
+/// `url` if it is safe to link to from rendered content: `http`, `https` and
+/// `mailto` URLs, and mirrored media. Anything else, such as `javascript:`,
+/// is dropped.
+fn safe_url(url: &str) -> Option<&str> {
+  let url: &str = url.trim();
+  let scheme: Option<String> = url
+    .split_once(':')
+    .map(|(scheme, _)| scheme.to_ascii_lowercase());
+
+  let safe: bool = match scheme.as_deref() {
+    Some("http" | "https" | "mailto") => true,
+    _ => url.starts_with("/media/")
+  };
+
+  safe.then_some(url)
+}
+
+/// Percent-encode what would end a Markdown link destination early.
+fn escape_markdown_url(url: &str) -> String {
+  let mut escaped: String = String::with_capacity(url.len());
+
+  for character in url.chars() {
+    match character {
+      ' ' => escaped.push_str("%20"),
+      '(' => escaped.push_str("%28"),
+      ')' => escaped.push_str("%29"),
+      '<' => escaped.push_str("%3C"),
+      '>' => escaped.push_str("%3E"),
+      _ => escaped.push(character)
+    }
+  }
+
+  escaped
+}
+
+/// Whether a block is left out of rendered content.
+fn is_skipped(kind: &BlockKind) -> bool {
+  match kind {
+    BlockKind::TableRow { .. } | BlockKind::Unsupported { .. } => true,
+    BlockKind::Image { url, .. } => safe_url(&url.url).is_none(),
+    _ => false
+  }
+}
+
+/// Split `text` into leading whitespace, content and trailing whitespace, so
+/// Markdown emphasis markers can hug the content.
+fn split_whitespace(text: &str) -> (&str, &str, &str) {
+  let content: &str = text.trim();
+  let start: usize = text.len() - text.trim_start().len();
+
+  (
+    &text[..start],
+    content,
+    &text[start + content.len()..]
+  )
+}
+
+
+pub fn span_to_markdown(span: &RichTextSpan) -> String {
+  if span.kind == SpanKind::Equation {
+    return format!("${}$", span.plain_text);
+  }
+
+  let (leading, content, trailing) = split_whitespace(&span.plain_text);
+
+  if content.is_empty() {
+    return span.plain_text.clone();
+  }
+
+  let annotations = &span.annotations;
+  let mut text: String = if annotations.code {
+    format!("`{content}`")
+  } else {
+    escape_markdown(content)
+  };
+
+  if annotations.strikethrough {
+    text = format!("~~{text}~~");
+  }
+  if annotations.italic {
+    text = format!("*{text}*");
+  }
+  if annotations.bold {
+    text = format!("**{text}**");
+  }
+  if annotations.underline {
+    text = format!("<u>{text}</u>");
+  }
+  if let Some(href) = span.href.as_deref().and_then(safe_url) {
+    text = format!("[{text}]({})", escape_markdown_url(href));
+  }
+
+  format!("{leading}{text}{trailing}")
+}
+
+pub fn rich_text_to_markdown(rich_text: &RichText) -> String {
+  rich_text.iter().map(span_to_markdown).collect()
+}
+
+pub fn span_to_html(span: &RichTextSpan) -> String {
+  let annotations = &span.annotations;
+  let mut text: String = escape_html(&span.plain_text).replace('\n', "<br>");
+
+  if span.kind == SpanKind::Equation {
+    text = format!(r#"<span class="equation">{text}</span>"#);
+  }
+  if annotations.code {
+    text = format!("<code>{text}</code>");
+  }
+  if annotations.strikethrough {
+    text = format!("<s>{text}</s>");
+  }
+  if annotations.underline {
+    text = format!("<u>{text}</u>");
+  }
+  if annotations.italic {
+    text = format!("<em>{text}</em>");
+  }
+  if annotations.bold {
+    text = format!("<strong>{text}</strong>");
+  }
+  if annotations.has_color() {
+    text = format!(
+      r#"<span class="color-{color}">{text}</span>"#,
+      color = escape_html(&annotations.color)
+    );
+  }
+  if let Some(href) = span.href.as_deref().and_then(safe_url) {
+    text = format!(
+      r#"<a href="{href}"{class}>{text}</a>"#,
+      href = escape_html(href),
+      class = if span.kind == SpanKind::Mention { r#" class="mention""# } else { "" }
+    );
+  }
+
+  text
+}
+
+pub fn rich_text_to_html(rich_text: &RichText) -> String {
+  rich_text.iter().map(span_to_html).collect()
+}
+
+
+/// Render a block tree as GitHub flavoured Markdown.
+pub fn to_markdown(blocks: &[Block]) -> String {
+  let mut output: String = String::new();
+
+  write_markdown(blocks, "", &mut output);
+
+  format!("{}\n", output.trim_end())
+}
+
+fn is_list_item(kind: &BlockKind) -> bool {
+  matches!(
+    kind,
+    BlockKind::BulletedListItem { .. }
+      | BlockKind::NumberedListItem { .. }
+      | BlockKind::ToDo { .. }
+      | BlockKind::Toggle { .. }
+  )
+}
+
+fn write_markdown(
+  blocks: &[Block],
+  indent: &str,
+  output: &mut String
+) {
+  let blank_line: String = format!("{}\n", indent.trim_end());
+  let mut number: usize = 0;
+  let mut previous_list_item: bool = false;
+
+  for block in blocks {
+    if is_skipped(&block.kind) {
+      continue;
+    }
+
+    let list_item: bool = is_list_item(&block.kind);
+    if previous_list_item && !list_item {
+      output.push_str(&blank_line);
+    }
+    previous_list_item = list_item;
+
+    number = match block.kind {
+      BlockKind::NumberedListItem { .. } => number + 1,
+      _ => 0
+    };
+
+    // Prefix of the first line, and of every following line of the block.
+    let (prefix, continuation): (String, String) = match &block.kind {
+      BlockKind::BulletedListItem { .. }
+        | BlockKind::ToDo { .. }
+        | BlockKind::Toggle { .. } => ("- ".into(), "  ".into()),
+      BlockKind::NumberedListItem { .. } => (
+        format!("{number}. "),
+        " ".repeat(number.to_string().len() + 2)
+      ),
+      BlockKind::Quote { .. } | BlockKind::Callout { .. } => ("> ".into(), "> ".into()),
+      _ => (String::new(), String::new())
+    };
+
+    let body: String = match &block.kind {
+      BlockKind::Paragraph { rich_text }
+        | BlockKind::BulletedListItem { rich_text }
+        | BlockKind::NumberedListItem { rich_text }
+        | BlockKind::Quote { rich_text }
+        | BlockKind::Toggle { rich_text } => rich_text_to_markdown(rich_text),
+      BlockKind::Heading { level, rich_text, .. } => format!(
+        "{} {}",
+        "#".repeat(*level as usize),
+        rich_text_to_markdown(rich_text)
+      ),
+      BlockKind::ToDo { rich_text, checked } => format!(
+        "[{}] {}",
+        if *checked { "x" } else { " " },
+        rich_text_to_markdown(rich_text)
+      ),
+      BlockKind::Code { text, language, caption } => {
+        let fence: String = "`".repeat(
+          text
+            .split(|character| character != '`')
+            .map(str::len)
+            .max()
+            .unwrap_or(0)
+            .max(2) + 1
+        );
+        let mut code: String = format!("{fence}{language}\n{text}\n{fence}");
+        if !caption.is_empty() {
+          code.push_str(&format!("\n\n{}", rich_text_to_markdown(caption)));
+        }
+        code
+      },
+      BlockKind::Image { url, caption } => format!(
+        "![{}]({})",
+        escape_markdown(&plain_text(caption)),
+        escape_markdown_url(url.url.trim())
+      ),
+      BlockKind::Callout { rich_text, icon } => match icon {
+        Some(icon) => format!("{icon} {}", rich_text_to_markdown(rich_text)),
+        None => rich_text_to_markdown(rich_text)
+      },
+      BlockKind::Divider => "---".into(),
+      BlockKind::Table { rows, .. } => {
+        let columns: usize = rows.iter().map(Vec::len).max().unwrap_or(0);
+        let mut table: Vec<String> = rows
+          .iter()
+          .map(
+            |row| format!(
+              "| {} |",
+              (0..columns)
+                .map(
+                  |column| row
+                    .get(column)
+                    .map(rich_text_to_markdown)
+                    .unwrap_or_default()
+                    .replace('\n', " ")
+                )
+                .collect::<Vec<_>>()
+                .join(" | ")
+            )
+          )
+          .collect();
+        if !table.is_empty() {
+          table.insert(1, format!("|{}", " --- |".repeat(columns)));
+        }
+        table.join("\n")
+      },
+      BlockKind::TableRow { .. } | BlockKind::Unsupported { .. } => continue
+    };
+
+    output.push_str(
+      &format!(
+        "{indent}{prefix}{}\n",
+        body.replace('\n', &format!("\n{indent}{continuation}"))
+      )
+    );
+
+    write_markdown(
+      &block.children,
+      &format!("{indent}{continuation}"),
+      output
+    );
+
+    if !list_item {
+      output.push_str(&blank_line);
+    }
+  }
+
+  if previous_list_item {
+    output.push_str(&blank_line);
+  }
+}
+
+
+/// Render a block tree as an HTML fragment.
+pub fn to_html(blocks: &[Block]) -> String {
+  let mut output: String = String::new();
+
+  write_html(blocks, &mut output);
+
+  output
+}
+
+fn write_html(
+  blocks: &[Block],
+  output: &mut String
+) {
+  // Consecutive list items share one list element.
+  let mut open_list: Option<&str> = None;
+
+  for block in blocks {
+    let list: Option<&str> = match block.kind {
+      BlockKind::BulletedListItem { .. } => Some("ul"),
+      BlockKind::NumberedListItem { .. } => Some("ol"),
+      BlockKind::ToDo { .. } => Some(r#"ul class="to-do""#),
+      _ => None
+    };
+
+    if open_list != list {
+      if let Some(tag) = open_list {
+        output.push_str(&format!("</{}>", tag.split(' ').next().unwrap_or(tag)));
+      }
+      if let Some(tag) = list {
+        output.push_str(&format!("<{tag}>"));
+      }
+      open_list = list;
+    }
+
+    let mut children: String = String::new();
+    write_html(&block.children, &mut children);
+
+    match &block.kind {
+      BlockKind::Paragraph { rich_text } => output.push_str(
+        &format!("<p>{}</p>{children}", rich_text_to_html(rich_text))
+      ),
+      BlockKind::Heading { level, rich_text, .. } => output.push_str(
+        &format!("<h{level}>{}</h{level}>{children}", rich_text_to_html(rich_text))
+      ),
+      BlockKind::BulletedListItem { rich_text }
+        | BlockKind::NumberedListItem { rich_text } => output.push_str(
+        &format!("<li>{}{children}</li>", rich_text_to_html(rich_text))
+      ),
+      BlockKind::ToDo { rich_text, checked } => output.push_str(
+        &format!(
+          r#"<li><input type="checkbox" disabled{}> {}{children}</li>"#,
+          if *checked { " checked" } else { "" },
+          rich_text_to_html(rich_text)
+        )
+      ),
+      BlockKind::Quote { rich_text } => output.push_str(
+        &format!("<blockquote>{}{children}</blockquote>", rich_text_to_html(rich_text))
+      ),
+      BlockKind::Code { text, language, caption } => {
+        output.push_str(
+          &format!(
+            r#"<pre><code class="language-{}">{}</code></pre>"#,
+            escape_html(&language.replace(' ', "-")),
+            escape_html(text)
+          )
+        );
+        if !caption.is_empty() {
+          output.push_str(&format!("<p>{}</p>", rich_text_to_html(caption)));
+        }
+      },
+      BlockKind::Image { url, .. } if safe_url(&url.url).is_none() => {},
+      BlockKind::Image { url, caption } => output.push_str(
+        &format!(
+          r#"<figure><img src="{}" alt="{}">{}</figure>"#,
+          escape_html(&url.url),
+          escape_html(&plain_text(caption)),
+          if caption.is_empty() {
+            String::new()
+          } else {
+            format!("<figcaption>{}</figcaption>", rich_text_to_html(caption))
+          }
+        )
+      ),
+      BlockKind::Callout { rich_text, icon } => output.push_str(
+        &format!(
+          r#"<aside class="callout">{}<div>{}{children}</div></aside>"#,
+          icon
+            .as_ref()
+            .map(|icon| format!(r#"<span class="callout-icon">{}</span>"#, escape_html(icon)))
+            .unwrap_or_default(),
+          rich_text_to_html(rich_text)
+        )
+      ),
+      BlockKind::Toggle { rich_text } => output.push_str(
+        &format!(
+          "<details><summary>{}</summary>{children}</details>",
+          rich_text_to_html(rich_text)
+        )
+      ),
+      BlockKind::Divider => output.push_str("<hr>"),
+      BlockKind::Table { has_column_header, has_row_header, rows } => {
+        output.push_str("<table>");
+        for (row_index, row) in rows.iter().enumerate() {
+          let header_row: bool = *has_column_header && row_index == 0;
+          if header_row {
+            output.push_str("<thead>");
+          } else if row_index == usize::from(*has_column_header) {
+            output.push_str("<tbody>");
+          }
+          output.push_str("<tr>");
+          for (column_index, cell) in row.iter().enumerate() {
+            let tag: &str = if header_row || (*has_row_header && column_index == 0) {
+              "th"
+            } else {
+              "td"
+            };
+            output.push_str(&format!("<{tag}>{}</{tag}>", rich_text_to_html(cell)));
+          }
+          output.push_str("</tr>");
+          if header_row {
+            output.push_str("</thead>");
+          }
+        }
+        if rows.len() > usize::from(*has_column_header) {
+          output.push_str("</tbody>");
+        }
+        output.push_str("</table>");
+      },
+      BlockKind::TableRow { .. } | BlockKind::Unsupported { .. } => {}
+    }
+  }
+
+  if let Some(tag) = open_list {
+    output.push_str(&format!("</{}>", tag.split(' ').next().unwrap_or(tag)));
+  }
+}
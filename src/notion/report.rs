@@ -0,0 +1,114 @@
+use std::{
+  collections::HashMap,
+  sync::OnceLock
+};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use tokio::sync::RwLock;
+
+use super::{types::NotionDataType, error::FieldError};
+
+
+pub static SYNC_REPORT: OnceLock<SyncReport> = OnceLock::new();
+
+
+/// Why a Notion page was left out of the cache.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all(serialize = "snake_case"))]
+pub struct ParseDiagnostic {
+  pub page_id: String,
+  pub page_url: Option<String>,
+  pub field: Option<String>,
+  pub reason: String
+}
+
+impl ParseDiagnostic {
+  pub fn new(
+    json_data: &Value,
+    error: &anyhow::Error
+  ) -> ParseDiagnostic {
+    let field_error: Option<&FieldError> = error.downcast_ref::<FieldError>();
+
+    ParseDiagnostic {
+      page_id: json_data["id"].as_str().unwrap_or("").into(),
+      page_url: json_data["url"].as_str().map(Into::into),
+      field: field_error.map(|error| error.path.clone()),
+      reason: match field_error {
+        Some(error) => error.reason.clone(),
+        None => format!("{error:#}")
+      }
+    }
+  }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all(serialize = "snake_case"))]
+pub struct TypeReport {
+  pub synced_at: DateTime<Utc>,
+  pub parsed: usize,
+  pub skipped: Vec<ParseDiagnostic>
+}
+
+/// Outcome of the latest fetch of each data type: the pages skipped by the
+/// latest complete fetch and every incremental fetch since, less those that
+/// were fetched again without problems.
+pub struct SyncReport {
+  reports: RwLock<HashMap<NotionDataType, TypeReport>>
+}
+
+impl SyncReport {
+  fn new() -> SyncReport {
+    SyncReport {
+      reports: RwLock::new(HashMap::new())
+    }
+  }
+
+  pub fn get() -> &'static SyncReport {
+    SYNC_REPORT.get_or_init(
+      SyncReport::new
+    )
+  }
+
+  pub async fn record(
+    self: &Self,
+    data_type: &NotionDataType,
+    parsed: usize,
+    skipped: Vec<ParseDiagnostic>
+  ) {
+    self.reports.write().await.insert(
+      data_type.clone(),
+      TypeReport {
+        synced_at: Utc::now(),
+        parsed,
+        skipped
+      }
+    );
+  }
+
+  /// Fold an incremental fetch, which returned the pages in `fetched_ids`,
+  /// into the report of a type. `parsed` is the number of records the type
+  /// now has.
+  pub async fn merge(
+    self: &Self,
+    data_type: &NotionDataType,
+    parsed: usize,
+    fetched_ids: &[String],
+    skipped: Vec<ParseDiagnostic>
+  ) {
+    let mut reports = self.reports.write().await;
+    let report: &mut TypeReport = reports.entry(data_type.clone()).or_default();
+
+    report.synced_at = Utc::now();
+    report.parsed = parsed;
+    report.skipped.retain(|diagnostic| !fetched_ids.contains(&diagnostic.page_id));
+    report.skipped.extend(skipped);
+  }
+
+  pub async fn request_all(
+    self: &Self
+  ) -> HashMap<NotionDataType, TypeReport> {
+    self.reports.read().await.clone()
+  }
+}
@@ -0,0 +1,90 @@
+use std::{
+  sync::OnceLock,
+  env,
+  time::Duration
+};
+
+use rand::Rng;
+
+
+static RETRY_POLICY: OnceLock<RetryPolicy> = OnceLock::new();
+
+
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+  pub max_attempts: u32,
+  pub base_delay: Duration,
+  pub max_delay: Duration,
+  /// Longest a single attempt may take, response body included.
+  pub timeout: Duration
+}
+
+impl Default for RetryPolicy {
+  fn default() -> RetryPolicy {
+    RetryPolicy {
+      max_attempts: 5,
+      base_delay: Duration::from_millis(500),
+      max_delay: Duration::from_secs(30),
+      timeout: Duration::from_secs(30)
+    }
+  }
+}
+
+impl RetryPolicy {
+  /// Policy used by the Notion client. Each field can be overridden with
+  /// `NOTION_RETRY_MAX_ATTEMPTS`, `NOTION_RETRY_BASE_DELAY_MS`,
+  /// `NOTION_RETRY_MAX_DELAY_MS` and `NOTION_REQUEST_TIMEOUT_MS`.
+  pub fn get() -> &'static RetryPolicy {
+    RETRY_POLICY.get_or_init(
+      || {
+        let default: RetryPolicy = RetryPolicy::default();
+
+        RetryPolicy {
+          max_attempts: env::var("NOTION_RETRY_MAX_ATTEMPTS")
+            .ok()
+            .and_then(|value| value.parse::<u32>().ok())
+            .unwrap_or(default.max_attempts)
+            .max(1),
+          base_delay: env::var("NOTION_RETRY_BASE_DELAY_MS")
+            .ok()
+            .and_then(|value| value.parse::<u64>().ok())
+            .map(Duration::from_millis)
+            .unwrap_or(default.base_delay),
+          max_delay: env::var("NOTION_RETRY_MAX_DELAY_MS")
+            .ok()
+            .and_then(|value| value.parse::<u64>().ok())
+            .map(Duration::from_millis)
+            .unwrap_or(default.max_delay),
+          timeout: env::var("NOTION_REQUEST_TIMEOUT_MS")
+            .ok()
+            .and_then(|value| value.parse::<u64>().ok())
+            .map(Duration::from_millis)
+            .unwrap_or(default.timeout)
+        }
+      }
+    )
+  }
+
+  /// Delay before retrying after the given (1-based) failed attempt.
+  ///
+  /// A `Retry-After` from the server is honoured up to `max_delay`;
+  /// otherwise the delay grows exponentially from `base_delay`, capped at
+  /// `max_delay`, with the upper half randomised so concurrent retries do not
+  /// line up.
+  pub fn delay(
+    self: &Self,
+    attempt: u32,
+    retry_after: Option<Duration>
+  ) -> Duration {
+    if let Some(retry_after) = retry_after {
+      return retry_after.min(self.max_delay);
+    }
+
+    let exponential: Duration = self.base_delay
+      .saturating_mul(2_u32.saturating_pow(attempt.saturating_sub(1)))
+      .min(self.max_delay);
+    let half: Duration = exponential / 2;
+
+    half + half.mul_f64(rand::thread_rng().gen::<f64>())
+  }
+}
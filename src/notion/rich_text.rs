@@ -0,0 +1,84 @@
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+
+
+pub type RichText = Vec<RichTextSpan>;
+
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpanKind {
+  Text, Mention, Equation
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all(serialize = "snake_case"))]
+pub struct Annotations {
+  pub bold: bool,
+  pub italic: bool,
+  pub strikethrough: bool,
+  pub underline: bool,
+  pub code: bool,
+  pub color: String
+}
+
+impl Annotations {
+  pub fn from_json(json_data: &Value) -> Annotations {
+    Annotations {
+      bold: json_data["bold"].as_bool().unwrap_or(false),
+      italic: json_data["italic"].as_bool().unwrap_or(false),
+      strikethrough: json_data["strikethrough"].as_bool().unwrap_or(false),
+      underline: json_data["underline"].as_bool().unwrap_or(false),
+      code: json_data["code"].as_bool().unwrap_or(false),
+      color: json_data["color"].as_str().unwrap_or("default").into()
+    }
+  }
+
+  pub fn has_color(self: &Self) -> bool {
+    !self.color.is_empty() && self.color != "default"
+  }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all(serialize = "snake_case"))]
+pub struct RichTextSpan {
+  #[serde(rename = "type")]
+  pub kind: SpanKind,
+  pub plain_text: String,
+  pub href: Option<String>,
+  pub annotations: Annotations
+}
+
+impl RichTextSpan {
+  pub fn from_json(json_data: &Value) -> RichTextSpan {
+    RichTextSpan {
+      kind: match json_data["type"].as_str() {
+        Some("mention") => SpanKind::Mention,
+        Some("equation") => SpanKind::Equation,
+        _ => SpanKind::Text
+      },
+      plain_text: json_data["plain_text"].as_str().unwrap_or("").into(),
+      href: json_data["href"].as_str().map(Into::into),
+      annotations: Annotations::from_json(&json_data["annotations"])
+    }
+  }
+}
+
+/// Parse a Notion rich text array, keeping every span and its annotations.
+/// Anything that is not an array is treated as empty text.
+pub fn spans(rich_text: &Value) -> RichText {
+  rich_text
+    .as_array()
+    .map(
+      |spans| spans.iter().map(RichTextSpan::from_json).collect()
+    )
+    .unwrap_or_default()
+}
+
+/// Concatenate the plain text of every span.
+pub fn plain_text(spans: &[RichTextSpan]) -> String {
+  spans
+    .iter()
+    .map(|span| span.plain_text.as_str())
+    .collect()
+}
@@ -0,0 +1,474 @@
+use std::{
+  collections::HashMap,
+  sync::{Arc, OnceLock},
+  env,
+  fs
+};
+
+use arc_swap::ArcSwap;
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+use anyhow::{Result, anyhow};
+
+use super::{
+  types::NotionDataType,
+  error::FieldError,
+  file::NotionFile,
+  rich_text::{self, RichText}
+};
+
+
+pub static SCHEMA: OnceLock<ArcSwap<Schema>> = OnceLock::new();
+static DEFAULT_SCHEMA: &str = include_str!("../../schema.toml");
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PropertyType {
+  Title,
+  RichText,
+  Select,
+  MultiSelect,
+  Files,
+  Url,
+  Date,
+  Relation,
+  CreatedTime,
+  LastEditedTime,
+  #[serde(rename = "cover")]
+  PageCover,
+  #[serde(rename = "icon")]
+  PageIcon
+}
+
+impl PropertyType {
+  /// Key of the property value in a Notion page, which is also the name of
+  /// the type in a database schema.
+  pub fn key(self: &Self) -> &'static str {
+    match self {
+      PropertyType::Title => "title",
+      PropertyType::RichText => "rich_text",
+      PropertyType::Select => "select",
+      PropertyType::MultiSelect => "multi_select",
+      PropertyType::Files => "files",
+      PropertyType::Url => "url",
+      PropertyType::Date => "date",
+      PropertyType::Relation => "relation",
+      PropertyType::CreatedTime => "created_time",
+      PropertyType::LastEditedTime => "last_edited_time",
+      PropertyType::PageCover => "cover",
+      PropertyType::PageIcon => "icon"
+    }
+  }
+
+  /// Cover and icon belong to the page itself rather than to a property.
+  pub fn is_page_level(self: &Self) -> bool {
+    matches!(self, PropertyType::PageCover | PropertyType::PageIcon)
+  }
+}
+
+const TEXT: &[PropertyType] = &[PropertyType::Title, PropertyType::RichText];
+const OPTIONS: &[PropertyType] = &[PropertyType::MultiSelect, PropertyType::Select];
+const FILES: &[PropertyType] = &[PropertyType::Files, PropertyType::PageCover, PropertyType::PageIcon];
+
+/// Fields each type reads, with the property types they can be read from.
+fn expected_fields(
+  data_type: &NotionDataType
+) -> &'static [(&'static str, &'static [PropertyType])] {
+  match data_type {
+    NotionDataType::Member => &[
+      ("avatar", FILES),
+      ("name", TEXT),
+      ("nickname", TEXT),
+      ("groups", &[PropertyType::Relation]),
+      ("description", TEXT),
+      ("club", &[PropertyType::Relation]),
+      ("club_positions", OPTIONS)
+    ],
+    NotionDataType::Group => &[
+      ("name", TEXT),
+      ("description", TEXT),
+      ("members", &[PropertyType::Relation])
+    ],
+    NotionDataType::Club => &[
+      ("name", TEXT),
+      ("description", TEXT),
+      ("school", &[PropertyType::Select, PropertyType::Title, PropertyType::RichText]),
+      ("instagram_id", TEXT),
+      ("icon", FILES)
+    ],
+    NotionDataType::Event => &[
+      ("date", &[PropertyType::Date]),
+      ("name", TEXT),
+      ("description", TEXT),
+      ("thumbnail", FILES),
+      ("principal", &[PropertyType::Relation])
+    ],
+    NotionDataType::Article => &[
+      ("title", TEXT),
+      ("description", TEXT),
+      ("tags", OPTIONS),
+      ("created_at", &[PropertyType::CreatedTime, PropertyType::Date]),
+      ("updated_at", &[PropertyType::LastEditedTime, PropertyType::Date])
+    ],
+    NotionDataType::Sponsor => &[
+      ("name", TEXT),
+      ("description", TEXT),
+      ("url", &[PropertyType::Url, PropertyType::RichText]),
+      ("icon", FILES)
+    ]
+  }
+}
+
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PropertyMapping {
+  /// Unused for page level types.
+  #[serde(default)]
+  pub property: String,
+  #[serde(rename = "type")]
+  pub property_type: PropertyType
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Schema {
+  #[serde(flatten)]
+  types: HashMap<NotionDataType, HashMap<String, PropertyMapping>>
+}
+
+impl Schema {
+  /// Schema loaded from `NOTION_SCHEMA_PATH`, or the `schema.toml` bundled
+  /// at build time.
+  pub fn get() -> Arc<Schema> {
+    get_storage().load_full()
+  }
+
+  /// Read `NOTION_SCHEMA_PATH` again and parse every later page with it.
+  #[cfg(test)]
+  pub fn reload() -> Result<()> {
+    get_storage().store(Arc::new(Schema::load()?));
+
+    Ok(())
+  }
+
+  fn load() -> Result<Schema> {
+    let source: String = match env::var("NOTION_SCHEMA_PATH") {
+      Ok(path) => fs::read_to_string(&path).map_err(
+        |error| anyhow!("Read schema `{path}` failed: {error}")
+      )?,
+      Err(_) => DEFAULT_SCHEMA.into()
+    };
+
+    Schema::parse(&source).map_err(
+      |error| anyhow!("Invalid schema: {error:#}")
+    )
+  }
+
+  pub fn parse(source: &str) -> Result<Schema> {
+    let schema: Schema = toml::from_str(source)?;
+
+    let problems: Vec<String> = schema.check();
+    if !problems.is_empty() {
+      return Err(anyhow!(problems.join(" ")));
+    }
+
+    Ok(schema)
+  }
+
+  /// Check that every field is mapped exactly once to a usable type.
+  fn check(self: &Self) -> Vec<String> {
+    let mut problems: Vec<String> = Vec::new();
+
+    for data_type in NotionDataType::iterator() {
+      let Some(fields) = self.types.get(&data_type) else {
+        problems.push(format!("Missing table `{data_type:?}`."));
+        continue;
+      };
+
+      for (field, accepted) in expected_fields(&data_type) {
+        match fields.get(*field) {
+          None => problems.push(
+            format!("Missing field `{data_type:?}.{field}`.")
+          ),
+          Some(mapping) if !accepted.contains(&mapping.property_type) => problems.push(
+            format!(
+              "Field `{data_type:?}.{field}` cannot be read from a `{}` property.",
+              mapping.property_type.key()
+            )
+          ),
+          Some(_) => {}
+        }
+      }
+
+      for field in fields.keys() {
+        if !expected_fields(&data_type).iter().any(|(name, _)| name == field) {
+          problems.push(format!("Unknown field `{data_type:?}.{field}`."));
+        }
+      }
+    }
+
+    problems
+  }
+
+  pub fn fields(
+    self: &Self,
+    data_type: &NotionDataType
+  ) -> &HashMap<String, PropertyMapping> {
+    // `check` guarantees every type has a table.
+    &self.types[data_type]
+  }
+
+  /// Compare the mapping of a type with the schema returned by
+  /// `/v1/databases/{id}` and describe every mismatch.
+  pub fn validate(
+    self: &Self,
+    data_type: &NotionDataType,
+    database: &Value
+  ) -> Vec<String> {
+    let mut problems: Vec<String> = Vec::new();
+
+    for (field, mapping) in self.fields(data_type) {
+      if mapping.property_type.is_page_level() {
+        continue;
+      }
+
+      let property: &Value = &database["properties"][&mapping.property];
+
+      match property["type"].as_str() {
+        None => problems.push(
+          format!(
+            "`{data_type:?}.{field}`: property `{}` does not exist.",
+            mapping.property
+          )
+        ),
+        Some(found) if found != mapping.property_type.key() => problems.push(
+          format!(
+            "`{data_type:?}.{field}`: property `{}` is `{found}`, expected `{}`.",
+            mapping.property,
+            mapping.property_type.key()
+          )
+        ),
+        Some(_) => {}
+      }
+    }
+
+    problems.sort();
+    problems
+  }
+}
+
+
+fn get_storage() -> &'static ArcSwap<Schema> {
+  SCHEMA.get_or_init(
+    || ArcSwap::from_pointee(
+      Schema::load().unwrap_or_else(|error| panic!("{error:#}"))
+    )
+  )
+}
+
+
+/// Reads the fields of one Notion page through the schema mapping.
+pub struct PropertyReader<'a> {
+  json_data: &'a Value,
+  data_type: NotionDataType,
+  schema: Arc<Schema>
+}
+
+impl<'a> PropertyReader<'a> {
+  pub fn new(
+    data_type: &NotionDataType,
+    json_data: &'a Value
+  ) -> PropertyReader<'a> {
+    PropertyReader {
+      json_data,
+      data_type: data_type.clone(),
+      schema: Schema::get()
+    }
+  }
+
+  pub fn id(self: &Self) -> Result<String> {
+    Ok(
+      self.json_data["id"]
+        .as_str()
+        .ok_or(
+          FieldError::new("id", "expected a string")
+        )?
+        .into()
+    )
+  }
+
+  /// When the page was last edited, which is never before any of its
+  /// properties were.
+  pub fn last_edited_time(self: &Self) -> Result<DateTime<Utc>> {
+    let time: &str = self.json_data["last_edited_time"]
+      .as_str()
+      .ok_or(
+        FieldError::new("last_edited_time", "expected a string")
+      )?;
+
+    Ok(
+      DateTime::parse_from_rfc3339(time)
+        .map_err(
+          |error| FieldError::new("last_edited_time", format!("expected an RFC 3339 timestamp: {error}"))
+        )?
+        .with_timezone(&Utc)
+    )
+  }
+
+  /// Value of the property mapped to `field`, with its path for diagnostics.
+  fn property(
+    self: &Self,
+    field: &str
+  ) -> Result<(&'a Value, PropertyType, String)> {
+    let mapping: &PropertyMapping = self.schema.fields(&self.data_type).get(field).ok_or(
+      FieldError::new(field, "not mapped in the schema")
+    )?;
+
+    if mapping.property_type.is_page_level() {
+      let key: &str = mapping.property_type.key();
+      return Ok((&self.json_data[key], mapping.property_type, key.into()));
+    }
+    let path: String = format!(
+      "properties.{}.{}",
+      mapping.property,
+      mapping.property_type.key()
+    );
+    let property: &Value = &self.json_data["properties"][&mapping.property];
+
+    if property.is_null() {
+      return Err(
+        FieldError::new(
+          format!("properties.{}", mapping.property),
+          "property does not exist"
+        ).into()
+      );
+    }
+
+    if let Some(found) = property["type"].as_str() {
+      if found != mapping.property_type.key() {
+        return Err(
+          FieldError::new(
+            format!("properties.{}", mapping.property),
+            format!("expected a `{}` property, found `{found}`", mapping.property_type.key())
+          ).into()
+        );
+      }
+    }
+
+    Ok(
+      (
+        &property[mapping.property_type.key()],
+        mapping.property_type,
+        path
+      )
+    )
+  }
+
+  pub fn rich_text(self: &Self, field: &str) -> Result<RichText> {
+    let (value, _, path) = self.property(field)?;
+
+    if !value.is_array() {
+      return Err(FieldError::new(path, "expected an array").into());
+    }
+
+    Ok(rich_text::spans(value))
+  }
+
+  /// Plain text of a text, select, url or timestamp property.
+  pub fn text(self: &Self, field: &str) -> Result<String> {
+    let (value, property_type, path) = self.property(field)?;
+
+    match property_type {
+      PropertyType::Title | PropertyType::RichText => Ok(
+        rich_text::plain_text(&self.rich_text(field)?)
+      ),
+      PropertyType::Select => Ok(
+        value["name"]
+          .as_str()
+          .ok_or(
+            FieldError::new(format!("{path}.name"), "expected a string")
+          )?
+          .into()
+      ),
+      PropertyType::Date => Ok(
+        value["start"]
+          .as_str()
+          .ok_or(
+            FieldError::new(format!("{path}.start"), "expected a string")
+          )?
+          .into()
+      ),
+      _ => Ok(
+        value
+          .as_str()
+          .ok_or(
+            FieldError::new(path, "expected a string")
+          )?
+          .into()
+      )
+    }
+  }
+
+  /// Option names of a select or multi-select property.
+  pub fn names(self: &Self, field: &str) -> Result<Vec<String>> {
+    let (value, property_type, path) = self.property(field)?;
+
+    if property_type == PropertyType::Select {
+      return Ok(
+        value["name"].as_str().map(|name| vec![name.into()]).unwrap_or_default()
+      );
+    }
+
+    Ok(
+      value
+        .as_array()
+        .ok_or(
+          FieldError::new(path, "expected an array")
+        )?
+        .iter()
+        .map(
+          |option: &Value| {
+            option["name"].as_str().unwrap_or("N/A").into()
+          }
+        )
+        .collect()
+    )
+  }
+
+  /// Ids of the pages in a relation property.
+  pub fn relation(self: &Self, field: &str) -> Result<Vec<String>> {
+    let (value, _, path) = self.property(field)?;
+
+    Ok(
+      value
+        .as_array()
+        .ok_or(
+          FieldError::new(path, "expected an array")
+        )?
+        .iter()
+        .filter_map(|relation| relation["id"].as_str().map(Into::into))
+        .collect()
+    )
+  }
+
+  /// First file of a files property, or the page cover or icon.
+  pub fn file(self: &Self, field: &str) -> Result<NotionFile> {
+    let (value, property_type, path) = self.property(field)?;
+
+    match property_type {
+      PropertyType::Files => NotionFile::from_json(&value[0], &format!("{path}[0]")),
+      _ => NotionFile::from_json(value, &path)
+    }
+  }
+
+  pub fn date(self: &Self, field: &str) -> Result<(&'a Value, String)> {
+    let (value, _, path) = self.property(field)?;
+
+    if !value.is_object() {
+      return Err(FieldError::new(path, "expected a date").into());
+    }
+
+    Ok((value, path))
+  }
+}
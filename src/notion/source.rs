@@ -0,0 +1,212 @@
+use std::{
+  future::Future,
+  pin::Pin,
+  sync::{Arc, OnceLock},
+  env,
+  path::PathBuf
+};
+
+use arc_swap::ArcSwap;
+use hyper::StatusCode;
+use serde_json::{Value, json};
+use tokio::fs;
+use tracing::log::info;
+
+use super::{
+  client::NotionApi,
+  error::NotionError,
+  types::NotionDataType
+};
+
+
+pub static DATA_SOURCE: OnceLock<ArcSwap<Box<dyn DataSource>>> = OnceLock::new();
+
+
+pub type SourceFuture<'a> = Pin<Box<dyn Future<Output = Result<Value, NotionError>> + Send + 'a>>;
+
+
+/// Where Notion responses come from. Every method returns one raw response
+/// body, shaped like the corresponding Notion API response.
+pub trait DataSource: Send + Sync {
+  /// `POST /v1/databases/{id}/query` with `body`, which carries
+  /// `page_size`, `start_cursor` and `filter`. Pages only include the
+  /// properties in `filter_properties`, or all of them when it is empty.
+  fn query_database<'a>(
+    self: &'a Self,
+    data_type: &'a NotionDataType,
+    body: &'a Value,
+    filter_properties: &'a [&'a str]
+  ) -> SourceFuture<'a>;
+
+  /// `GET /v1/blocks/{id}/children`.
+  fn block_children<'a>(
+    self: &'a Self,
+    block_id: &'a str,
+    page_size: u8,
+    start_cursor: Option<&'a str>
+  ) -> SourceFuture<'a>;
+
+  /// `GET /v1/databases/{id}`.
+  fn retrieve_database<'a>(
+    self: &'a Self,
+    data_type: &'a NotionDataType
+  ) -> SourceFuture<'a>;
+
+  /// `GET /v1/pages/{id}`. Archived pages are still returned, with
+  /// `archived` set.
+  fn retrieve_page<'a>(
+    self: &'a Self,
+    page_id: &'a str
+  ) -> SourceFuture<'a>;
+}
+
+/// The live Notion API, or the fixture directory in `NOTION_FIXTURE_DIR`
+/// when it is set.
+///
+/// `*_DATABASE_ID` are required either way: they tell which type a page
+/// retrieved on its own, or named by a webhook event, belongs to.
+pub fn get() -> Arc<Box<dyn DataSource>> {
+  get_storage().load_full()
+}
+
+/// Answer every later request from `source` instead.
+#[cfg(test)]
+pub fn replace(source: Box<dyn DataSource>) {
+  get_storage().store(Arc::new(source));
+}
+
+fn get_storage() -> &'static ArcSwap<Box<dyn DataSource>> {
+  DATA_SOURCE.get_or_init(
+    || ArcSwap::from_pointee(
+      match env::var("NOTION_FIXTURE_DIR") {
+        Ok(directory) => {
+          info!("Serving Notion responses recorded in `{directory}`.");
+          Box::new(FixtureSource::new(directory.into()))
+        },
+        Err(_) => Box::new(NotionApi)
+      }
+    )
+  )
+}
+
+
+/// Recorded Notion responses in a directory, laid out as:
+///
+/// - `<type>/database.json`: the database object.
+/// - `<type>/query.json`: the first page of the database query, and
+///   `<type>/query-<cursor>.json` for the page after `next_cursor`.
+/// - `blocks/<id>.json`: the children of a page or block, and
+///   `blocks/<id>-<cursor>.json` for further pages. A missing file means
+///   the block has no children.
+/// - `pages/<id>.json`: a single page. A missing file is answered like
+///   Notion answers an unknown page, with `404 Not Found`.
+///
+/// `<type>` is the snake case name of the data type, e.g. `member`.
+pub struct FixtureSource {
+  directory: PathBuf
+}
+
+impl FixtureSource {
+  pub fn new(directory: PathBuf) -> FixtureSource {
+    FixtureSource { directory }
+  }
+
+  async fn read(
+    self: &Self,
+    path: PathBuf
+  ) -> Result<Option<Value>, NotionError> {
+    let bytes: Vec<u8> = match fs::read(self.directory.join(&path)).await {
+      Ok(bytes) => bytes,
+      Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+      Err(error) => return Err(
+        NotionError::Decode(format!("Read fixture `{}` failed: {error}", path.display()))
+      )
+    };
+
+    serde_json::from_slice(&bytes).map(Some).map_err(
+      |error| NotionError::Decode(format!("Parse fixture `{}` failed: {error}", path.display()))
+    )
+  }
+
+  async fn read_required(
+    self: &Self,
+    path: PathBuf
+  ) -> Result<Value, NotionError> {
+    self.read(path.clone()).await?.ok_or(
+      NotionError::Decode(format!("Fixture `{}` does not exist.", path.display()))
+    )
+  }
+}
+
+fn page_file_name(name: &str, start_cursor: Option<&str>) -> String {
+  match start_cursor {
+    Some(cursor) => format!("{name}-{cursor}.json"),
+    None => format!("{name}.json")
+  }
+}
+
+impl DataSource for FixtureSource {
+  fn query_database<'a>(
+    self: &'a Self,
+    data_type: &'a NotionDataType,
+    body: &'a Value,
+    _filter_properties: &'a [&'a str]
+  ) -> SourceFuture<'a> {
+    // Recordings are not filtered, so every sync sees every page.
+    Box::pin(
+      self.read_required(
+        PathBuf::from(data_type.name()).join(
+          page_file_name("query", body["start_cursor"].as_str())
+        )
+      )
+    )
+  }
+
+  fn block_children<'a>(
+    self: &'a Self,
+    block_id: &'a str,
+    _page_size: u8,
+    start_cursor: Option<&'a str>
+  ) -> SourceFuture<'a> {
+    Box::pin(
+      async move {
+        Ok(
+          self.read(
+            PathBuf::from("blocks").join(page_file_name(block_id, start_cursor))
+          ).await?.unwrap_or(
+            json!({"results": [], "has_more": false})
+          )
+        )
+      }
+    )
+  }
+
+  fn retrieve_database<'a>(
+    self: &'a Self,
+    data_type: &'a NotionDataType
+  ) -> SourceFuture<'a> {
+    Box::pin(
+      self.read_required(
+        PathBuf::from(data_type.name()).join("database.json")
+      )
+    )
+  }
+
+  fn retrieve_page<'a>(
+    self: &'a Self,
+    page_id: &'a str
+  ) -> SourceFuture<'a> {
+    Box::pin(
+      async move {
+        self.read(
+          PathBuf::from("pages").join(page_file_name(page_id, None))
+        ).await?.ok_or(
+          NotionError::Status {
+            status: StatusCode::NOT_FOUND,
+            body: json!({"object": "error", "code": "object_not_found"}).to_string()
+          }
+        )
+      }
+    )
+  }
+}
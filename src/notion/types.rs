@@ -1,10 +1,18 @@
 use std::{sync::{Arc, OnceLock}, env};
 
-use serde::Serialize;
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
 use serde_json::Value;
-use anyhow::{Result, anyhow};
+use anyhow::Result;
 
-use super::cache::CacheStorage;
+use super::{
+  error::FieldError,
+  blocks::Block,
+  file::NotionFile,
+  rich_text::{self, RichText},
+  render::{self, ContentFormat},
+  schema::PropertyReader
+};
 
 
 pub static MEMBER_DATABASE_ID: OnceLock<Arc<str>> = OnceLock::new();
@@ -15,8 +23,8 @@ pub static ARTICLE_DATABASE_ID: OnceLock<Arc<str>> = OnceLock::new();
 pub static SPONSOR_DATABASE_ID: OnceLock<Arc<str>> = OnceLock::new();
 
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
-#[serde(rename_all(serialize = "snake_case"))]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum NotionDataType {
   Member, Group, Club, Event, Article, Sponsor
 }
@@ -34,6 +42,76 @@ impl NotionDataType {
       DIRECTIONS.clone().into_iter()
   }
 
+  /// Snake case name, as used in urls and file names.
+  pub fn name(self: &Self) -> &'static str {
+    match self {
+      NotionDataType::Member => "member",
+      NotionDataType::Group => "group",
+      NotionDataType::Club => "club",
+      NotionDataType::Event => "event",
+      NotionDataType::Article => "article",
+      NotionDataType::Sponsor => "sponsor"
+    }
+  }
+
+  /// Relations of the records of the type, by field, with the type of the
+  /// related records.
+  pub fn relations(self: &Self) -> &'static [(&'static str, NotionDataType)] {
+    match self {
+      NotionDataType::Member => &[("groups", NotionDataType::Group), ("club", NotionDataType::Club)],
+      NotionDataType::Group => &[("members", NotionDataType::Member)],
+      NotionDataType::Event => &[("principal", NotionDataType::Member)],
+      NotionDataType::Club | NotionDataType::Article | NotionDataType::Sponsor => &[]
+    }
+  }
+
+  /// Top-level fields of the records of the type, as serialized.
+  pub fn fields(self: &Self) -> Vec<String> {
+    let record: NotionData = match self {
+      NotionDataType::Member => NotionData::Member(Member::default()),
+      NotionDataType::Group => NotionData::Group(Group::default()),
+      NotionDataType::Club => NotionData::Club(Club::default()),
+      NotionDataType::Event => NotionData::Event(Event::default()),
+      NotionDataType::Article => NotionData::Article(Article::default()),
+      NotionDataType::Sponsor => NotionData::Sponsor(Sponsor::default())
+    };
+
+    match serde_json::to_value(record) {
+      Ok(Value::Object(fields)) => fields.into_iter().map(|(field, _)| field).collect(),
+      _ => Vec::new()
+    }
+  }
+
+  /// Fields the records of the type can be listed in the order of.
+  pub fn sortable_fields(self: &Self) -> &'static [&'static str] {
+    match self {
+      NotionDataType::Member => &["id", "name", "nickname", "last_edited_time"],
+      NotionDataType::Group => &["id", "name", "last_edited_time"],
+      NotionDataType::Club => &["id", "name", "school", "last_edited_time"],
+      NotionDataType::Event => &["id", "name", "date.start", "date.end", "last_edited_time"],
+      NotionDataType::Article => &["id", "title", "created_at", "updated_at", "last_edited_time"],
+      NotionDataType::Sponsor => &["id", "name", "last_edited_time"]
+    }
+  }
+
+  /// Type with the given name, singular or plural as in the routes, e.g.
+  /// `member` or `members`.
+  pub fn from_name(name: &str) -> Option<NotionDataType> {
+    let name: &str = name.strip_suffix('s').unwrap_or(name);
+
+    NotionDataType::iterator().find(|data_type| data_type.name() == name)
+  }
+
+  /// Type whose database has the given id, with or without dashes.
+  pub fn from_database_id(database_id: &str) -> Option<NotionDataType> {
+    let normalize = |id: &str| id.replace('-', "").to_lowercase();
+    let database_id: String = normalize(database_id);
+
+    NotionDataType::iterator().find(
+      |data_type| normalize(data_type.get_databse_id()) == database_id
+    )
+  }
+
   pub fn get_databse_id(self: &Self) -> &str {
     match self {
       NotionDataType::Member => MEMBER_DATABASE_ID.get_or_init(
@@ -83,6 +161,7 @@ impl NotionDataType {
 }
 
 
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug, Clone, Serialize)]
 #[serde(untagged, rename_all(serialize = "snake_case"))]
 pub enum NotionData {
@@ -94,7 +173,102 @@ pub enum NotionData {
   Sponsor(Sponsor)
 }
 
-#[derive(Debug, Clone, Default, Serialize)]
+impl NotionData {
+  /// Newest edit of the record or of any record embedded in it.
+  pub fn last_edited_time(self: &Self) -> DateTime<Utc> {
+    match self {
+      NotionData::Member(member) => member.newest_edit(),
+      NotionData::Group(group) => group.members
+        .iter()
+        .flatten()
+        .map(|member| member.last_edited_time)
+        .fold(group.last_edited_time, DateTime::max),
+      NotionData::Club(club) => club.last_edited_time,
+      NotionData::Event(event) => event.principal
+        .iter()
+        .map(Member::newest_edit)
+        .fold(event.last_edited_time, DateTime::max),
+      NotionData::Article(article) => article.last_edited_time,
+      NotionData::Sponsor(sponsor) => sponsor.last_edited_time
+    }
+  }
+
+  /// Values of the fields the records of a type can be filtered by, as
+  /// `(field, value)` pairs. Event dates are reduced to the day.
+  pub fn index_values(self: &Self) -> Vec<(&'static str, String)> {
+    let day = |date: &str| -> String { date.get(..10).unwrap_or(date).into() };
+
+    match self {
+      NotionData::Member(member) => member.club_id
+        .iter()
+        .map(|id| ("club", id.clone()))
+        .chain(member.group_ids.iter().map(|id| ("group", id.clone())))
+        .chain(member.club_positions.iter().map(|position| ("position", position.clone())))
+        .collect(),
+      NotionData::Club(club) => vec![("school", club.school.clone())],
+      NotionData::Event(event) => vec![
+        ("start", day(&event.date.start)),
+        (
+          "end",
+          day(if event.date.end.is_empty() { &event.date.start } else { &event.date.end })
+        )
+      ],
+      NotionData::Article(article) => article.tags
+        .iter()
+        .map(|tag| ("tag", tag.clone()))
+        .collect(),
+      NotionData::Group(_) | NotionData::Sponsor(_) => Vec::new()
+    }
+  }
+
+  pub fn id(self: &Self) -> &str {
+    match self {
+      NotionData::Member(data) => &data.id,
+      NotionData::Group(data) => &data.id,
+      NotionData::Club(data) => &data.id,
+      NotionData::Event(data) => &data.id,
+      NotionData::Article(data) => &data.id,
+      NotionData::Sponsor(data) => &data.id
+    }
+  }
+
+  /// Every file referenced by the record itself, excluding related records.
+  pub fn files(self: &Self) -> Vec<&NotionFile> {
+    match self {
+      NotionData::Member(data) => vec![&data.avatar],
+      NotionData::Group(_) => Vec::new(),
+      NotionData::Club(data) => vec![&data.icon],
+      NotionData::Event(data) => vec![&data.thumbnail],
+      NotionData::Article(data) => match &data.content {
+        ArticleContent::Blocks(blocks) => blocks
+          .iter()
+          .flat_map(Block::files)
+          .collect(),
+        ArticleContent::Rendered(_) => Vec::new()
+      },
+      NotionData::Sponsor(data) => vec![&data.icon]
+    }
+  }
+
+  pub fn files_mut(self: &mut Self) -> Vec<&mut NotionFile> {
+    match self {
+      NotionData::Member(data) => vec![&mut data.avatar],
+      NotionData::Group(_) => Vec::new(),
+      NotionData::Club(data) => vec![&mut data.icon],
+      NotionData::Event(data) => vec![&mut data.thumbnail],
+      NotionData::Article(data) => match &mut data.content {
+        ArticleContent::Blocks(blocks) => blocks
+          .iter_mut()
+          .flat_map(Block::files_mut)
+          .collect(),
+        ArticleContent::Rendered(_) => Vec::new()
+      },
+      NotionData::Sponsor(data) => vec![&mut data.icon]
+    }
+  }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 #[serde(rename_all(serialize = "snake_case"))]
 pub struct EventPeriod {
   start: String,
@@ -102,422 +276,299 @@ pub struct EventPeriod {
 }
 
 impl EventPeriod {
-  pub fn from_json(json_data: &Value) -> Result<EventPeriod> {
+  pub fn from_json(
+    json_data: &Value,
+    path: &str
+  ) -> Result<EventPeriod> {
     Ok(
       EventPeriod {
         start: json_data["start"]
           .as_str()
           .ok_or(
-            anyhow!("Get `start` failed.")
-          )?
-          .into(),
-        end: json_data["end"]
-          .as_str()
-          .ok_or(
-            anyhow!("Get `end` failed.")
+            FieldError::new(format!("{path}.start"), "expected a string")
           )?
           .into(),
+        // Single-day events have no end.
+        end: match &json_data["end"] {
+          Value::Null => String::new(),
+          end => end
+            .as_str()
+            .ok_or(
+              FieldError::new(format!("{path}.end"), "expected a string or null")
+            )?
+            .into()
+        }
       }
     )
   }
 }
 
-#[derive(Debug, Clone, Default, Serialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 #[serde(rename_all(serialize = "snake_case"))]
 pub struct Member {
   pub id: String,
-  pub avatar: String,
+  #[serde(default)]
+  pub last_edited_time: DateTime<Utc>,
+  pub avatar: NotionFile,
   pub name: String,
   pub nickname: String,
   pub groups: Option<Vec<Group>>,
+  #[serde(skip)]
+  pub group_ids: Vec<String>,
   pub description: String,
+  pub description_rich_text: RichText,
   pub club: Option<Club>,
+  #[serde(skip)]
+  pub club_id: Option<String>,
   pub club_positions: Vec<String>
 }
 
 impl Member {
-  pub async fn from_json(json_data: &Value) -> Result<Member> {
-    let properties: &Value = &json_data["properties"];
-
-    let mut groups: Vec<Group> = Vec::new();
-    for groups_data in properties["groups"]["relation"].as_array().ok_or(
-      anyhow!("Get `groups` failed.")
-    )?.into_iter() {
-      groups.push(
-        match CacheStorage::get().request(
-          groups_data["id"].as_str().unwrap_or(""),
-          &NotionDataType::Group
-        ).await {
-          Some(data) => match data {
-            NotionData::Group(mut data) => {
-              data.members = None;
-              data
-            },
-            _ => Group::default()
-          },
-          None => Group::default()
-        }
-      );
-    }
+  pub fn from_json(json_data: &Value) -> Result<Member> {
+    let properties: PropertyReader = PropertyReader::new(
+      &NotionDataType::Member,
+      json_data
+    );
+    let description: RichText = properties.rich_text("description")?;
 
     Ok(
       Member {
-        id: json_data["id"]
-          .as_str()
-          .ok_or(
-            anyhow!("Get `id` failed.")
-          )?
-          .into(),
-        avatar: properties["avatar"]["files"][0]["external"]["url"]
-          .as_str()
-          .ok_or(
-            anyhow!("Get `avatar` failed.")
-          )?
-          .into(),
-        name: properties["name"]["title"][0]["plain_text"]
-          .as_str()
-          .ok_or(
-            anyhow!("Get `name` failed.")
-          )?
-          .into(),
-        nickname: properties["nickname"]["rich_text"][0]["plain_text"]
-          .as_str()
-          .ok_or(
-            anyhow!("Get `nickname` failed.")
-          )?
-          .into(),
-        groups: Some(groups),
-        description: properties["description"]["rich_text"][0]["plain_text"]
-          .as_str()
-          .ok_or(
-            anyhow!("Get `description` failed.")
-          )?
-          .into(),
-        club: match CacheStorage::get().request(
-          properties["club"]["relation"][0]["id"].as_str().unwrap_or(""),
-            &NotionDataType::Club
-          ).await {
-            Some(data) => match data {
-              NotionData::Club(data) => Some(data),
-              _ => None
-            },
-            None => None
-          },
-        club_positions: properties["club_positions"]["multi_select"]
-          .as_array()
-          .ok_or(
-            anyhow!("Get `club_positions` failed.")
-          )?
-          .into_iter()
-          .map(
-            |d: &Value| {
-              d["name"].as_str().unwrap_or("N/A").into()
-            }
-          )
-          .collect()
+        id: properties.id()?,
+        last_edited_time: properties.last_edited_time()?,
+        avatar: properties.file("avatar")?,
+        name: properties.text("name")?,
+        nickname: properties.text("nickname")?,
+        groups: None,
+        group_ids: properties.relation("groups")?,
+        description: rich_text::plain_text(&description),
+        description_rich_text: description,
+        club: None,
+        club_id: properties.relation("club")?.into_iter().next(),
+        club_positions: properties.names("club_positions")?
       }
     )
   }
+
+  /// Newest edit of the member, its club and its groups.
+  fn newest_edit(self: &Self) -> DateTime<Utc> {
+    self.club
+      .iter()
+      .map(|club| club.last_edited_time)
+      .chain(self.groups.iter().flatten().map(|group| group.last_edited_time))
+      .fold(self.last_edited_time, DateTime::max)
+  }
 }
 
-#[derive(Debug, Clone, Default, Serialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 #[serde(rename_all(serialize = "snake_case"))]
 pub struct Group {
   pub id: String,
+  #[serde(default)]
+  pub last_edited_time: DateTime<Utc>,
   name: String,
   description: String,
-  members: Option<Vec<Member>>
+  description_rich_text: RichText,
+  pub members: Option<Vec<Member>>,
+  #[serde(skip)]
+  pub member_ids: Vec<String>
 }
 
 impl Group {
-  pub async fn from_json(json_data: &Value) -> Result<Group> {
-    let properties: &Value = &json_data["properties"];
-
-    let mut members: Vec<Member> = Vec::new();
-    for members_data in properties["members"]["relation"].as_array().ok_or(
-      anyhow!("Get `members` failed.")
-    )?.into_iter() {
-      members.push(
-        match CacheStorage::get().request(
-          members_data["id"].as_str().unwrap_or(""),
-          &NotionDataType::Member
-        ).await {
-          Some(data) => match data {
-            NotionData::Member(mut data) => {
-              data.groups = None;
-              data
-            },
-            _ => Member::default()
-          },
-          None => Member::default()
-        }
-      );
-    }
+  pub fn from_json(json_data: &Value) -> Result<Group> {
+    let properties: PropertyReader = PropertyReader::new(
+      &NotionDataType::Group,
+      json_data
+    );
+    let description: RichText = properties.rich_text("description")?;
 
     Ok(
       Group {
-        id: json_data["id"]
-          .as_str()
-          .ok_or(
-            anyhow!("Get `id` failed.")
-          )?
-          .into(),
-        name: properties["name"]["title"][0]["plain_text"]
-          .as_str()
-          .ok_or(
-            anyhow!("Get `name` failed.")
-          )?
-          .into(),
-        description: properties["description"]["rich_text"][0]["plain_text"]
-          .as_str()
-          .ok_or(
-            anyhow!("Get `description` failed.")
-          )?
-          .into(),
-        members: Some(members)
+        id: properties.id()?,
+        last_edited_time: properties.last_edited_time()?,
+        name: properties.text("name")?,
+        description: rich_text::plain_text(&description),
+        description_rich_text: description,
+        members: None,
+        member_ids: properties.relation("members")?
       }
     )
   }
 }
 
-#[derive(Debug, Clone, Default, Serialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 #[serde(rename_all(serialize = "snake_case"))]
 pub struct Club {
   pub id: String,
+  #[serde(default)]
+  pub last_edited_time: DateTime<Utc>,
   name: String,
   description: String,
+  description_rich_text: RichText,
   school: String,
   instagram_id: String,
-  icon: String
+  icon: NotionFile
 }
 
 impl Club {
-  pub async fn from_json(json_data: &Value) -> Result<Club> {
-    let properties: &Value = &json_data["properties"];
+  pub fn from_json(json_data: &Value) -> Result<Club> {
+    let properties: PropertyReader = PropertyReader::new(
+      &NotionDataType::Club,
+      json_data
+    );
+    let description: RichText = properties.rich_text("description")?;
 
     Ok(
       Club {
-        id: json_data["id"]
-          .as_str()
-          .ok_or(
-            anyhow!("Get `id` failed.")
-          )?
-          .into(),
-        name: properties["name"]["title"][0]["plain_text"]
-          .as_str()
-          .ok_or(
-            anyhow!("Get `name` failed.")
-          )?
-          .into(),
-        description: properties["description"]["rich_text"][0]["plain_text"]
-          .as_str()
-          .ok_or(
-            anyhow!("Get `description` failed.")
-          )?
-          .into(),
-        school: properties["school"]["select"]["name"]
-          .as_str()
-          .ok_or(
-            anyhow!("Get `school` failed.")
-          )?
-          .into(),
-        instagram_id: properties["instagram_id"]["rich_text"][0]["plain_text"]
-          .as_str()
-          .ok_or(
-            anyhow!("Get `instagram_id` failed.")
-          )?
-          .into(),
-        icon: properties["icon"]["files"][0]["external"]["url"]
-          .as_str()
-          .ok_or(
-            anyhow!("Get `icon` failed.")
-          )?
-          .into(),
+        id: properties.id()?,
+        last_edited_time: properties.last_edited_time()?,
+        name: properties.text("name")?,
+        description: rich_text::plain_text(&description),
+        description_rich_text: description,
+        school: properties.text("school")?,
+        instagram_id: properties.text("instagram_id")?,
+        icon: properties.file("icon")?
       }
     )
   }
 }
 
-#[derive(Debug, Clone, Default, Serialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 #[serde(rename_all(serialize = "snake_case"))]
 pub struct Event {
   pub id: String,
+  #[serde(default)]
+  pub last_edited_time: DateTime<Utc>,
   date: EventPeriod,
   name: String,
   description: String,
-  thumbnail: String,
-  principal: Vec<Member>
+  description_rich_text: RichText,
+  thumbnail: NotionFile,
+  pub principal: Vec<Member>,
+  #[serde(skip)]
+  pub principal_ids: Vec<String>
 }
 
 impl Event {
-  pub async fn from_json(json_data: &Value) -> Result<Event> {
-    let properties: &Value = &json_data["properties"];
-
-    let mut principal: Vec<Member> = Vec::new();
-    for principal_data in properties["principal"]["relation"].as_array().ok_or(
-      anyhow!("Get `principal` failed.")
-    )?.into_iter() {
-      principal.push(
-        match CacheStorage::get().request(
-          principal_data["id"].as_str().unwrap_or(""),
-          &NotionDataType::Member
-        ).await {
-          Some(data) => match data {
-            NotionData::Member(data) => data,
-            _ => Member::default()
-          },
-          None => Member::default()
-        }
-      )
-    }
+  pub fn from_json(json_data: &Value) -> Result<Event> {
+    let properties: PropertyReader = PropertyReader::new(
+      &NotionDataType::Event,
+      json_data
+    );
+    let description: RichText = properties.rich_text("description")?;
+    let (date, date_path) = properties.date("date")?;
 
     Ok(
       Event {
-        id: json_data["id"]
-          .as_str()
-          .ok_or(
-            anyhow!("Get `id` failed.")
-          )?
-          .into(),
-        date: EventPeriod::from_json(
-          &properties["date"]["date"]
-        )?,
-        name: properties["name"]["title"][0]["plain_text"]
-          .as_str()
-          .ok_or(
-            anyhow!("Get `name` failed.")
-          )?
-          .into(),
-        description: properties["description"]["rich_text"][0]["plain_text"]
-          .as_str()
-          .ok_or(
-            anyhow!("Get `description` failed.")
-          )?
-          .into(),
-        thumbnail: properties["thumbnail"]["files"][0]["external"]["url"]
-          .as_str()
-          .ok_or(
-            anyhow!("Get `thumbnail` failed.")
-          )?
-          .into(),
-        principal
+        id: properties.id()?,
+        last_edited_time: properties.last_edited_time()?,
+        date: EventPeriod::from_json(date, &date_path)?,
+        name: properties.text("name")?,
+        description: rich_text::plain_text(&description),
+        description_rich_text: description,
+        thumbnail: properties.file("thumbnail")?,
+        principal: Vec::new(),
+        principal_ids: properties.relation("principal")?
       }
     )
   }
 }
 
-#[derive(Debug, Clone, Default, Serialize)]
+/// Body of an article: the block tree as fetched, or rendered to a string.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ArticleContent {
+  Blocks(Vec<Block>),
+  Rendered(String)
+}
+
+impl Default for ArticleContent {
+  fn default() -> ArticleContent {
+    ArticleContent::Blocks(Vec::new())
+  }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 #[serde(rename_all(serialize = "snake_case"))]
 pub struct Article {
   pub id: String,
+  #[serde(default)]
+  pub last_edited_time: DateTime<Utc>,
   title: String,
-  content: String,
+  pub content: ArticleContent,
   description: String,
+  description_rich_text: RichText,
   tags: Vec<String>,
   created_at: String,
   updated_at: String
 }
 
 impl Article {
-  pub async fn from_json(json_data: &Value) -> Result<Article> {
-    let properties: &Value = &json_data["properties"];
+  pub fn from_json(json_data: &Value) -> Result<Article> {
+    let properties: PropertyReader = PropertyReader::new(
+      &NotionDataType::Article,
+      json_data
+    );
+    let description: RichText = properties.rich_text("description")?;
 
     Ok(
       Article {
-        id: json_data["id"]
-          .as_str()
-          .ok_or(
-            anyhow!("Get `id` failed.")
-          )?
-          .into(),
-        title: properties["title"]["title"][0]["plain_text"]
-          .as_str()
-          .ok_or(
-            anyhow!("Get `title` failed.")
-          )?
-          .into(),
-        description: properties["description"]["rich_text"][0]["plain_text"]
-          .as_str()
-          .ok_or(
-            anyhow!("Get `description` failed.")
-          )?
-          .into(),
-        content: "Todo".into(),
-        tags: properties["tags"]["multi_select"]
-          .as_array()
-          .ok_or(
-            anyhow!("Get `tags` failed.")
-          )?
-          .into_iter()
-          .map(
-            |d| {
-              d["name"].as_str().unwrap_or("N/A").into()
-            }
-          )
-          .collect(),
-        created_at: properties["created_at"]["created_time"]
-          .as_str()
-          .ok_or(
-            anyhow!("Get `created_at` failed.")
-          )?
-          .into(),
-          updated_at: properties["updated_at"]["last_edited_time"]
-          .as_str()
-          .ok_or(
-            anyhow!("Get `updated_at` failed.")
-          )?
-          .into(),
+        id: properties.id()?,
+        last_edited_time: properties.last_edited_time()?,
+        title: properties.text("title")?,
+        description: rich_text::plain_text(&description),
+        description_rich_text: description,
+        content: ArticleContent::default(),
+        tags: properties.names("tags")?,
+        created_at: properties.text("created_at")?,
+        updated_at: properties.text("updated_at")?
       }
     )
   }
+
+  /// Convert the block tree in `content` to the requested format.
+  pub fn render(mut self, format: ContentFormat) -> Article {
+    if let ArticleContent::Blocks(blocks) = &self.content {
+      self.content = match format {
+        ContentFormat::Blocks => return self,
+        ContentFormat::Html => ArticleContent::Rendered(render::to_html(blocks)),
+        ContentFormat::Markdown => ArticleContent::Rendered(render::to_markdown(blocks))
+      };
+    }
+
+    self
+  }
 }
 
-#[derive(Debug, Clone, Default, Serialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 #[serde(rename_all(serialize = "snake_case"))]
 pub struct Sponsor {
   pub id: String,
+  #[serde(default)]
+  pub last_edited_time: DateTime<Utc>,
   name: String,
-  icon: String,
+  icon: NotionFile,
   url: String,
-  description: String
+  description: String,
+  description_rich_text: RichText
 }
 
 impl Sponsor {
-  pub async fn from_json(json_data: &Value) -> Result<Sponsor> {
-    let properties: &Value = &json_data["properties"];
+  pub fn from_json(json_data: &Value) -> Result<Sponsor> {
+    let properties: PropertyReader = PropertyReader::new(
+      &NotionDataType::Sponsor,
+      json_data
+    );
+    let description: RichText = properties.rich_text("description")?;
 
     Ok(
       Sponsor {
-        id: json_data["id"]
-          .as_str()
-          .ok_or(
-            anyhow!("Get `id` failed.")
-          )?
-          .into(),
-        name: properties["name"]["title"][0]["plain_text"]
-          .as_str()
-          .ok_or(
-            anyhow!("Get `name` failed.")
-          )?
-          .into(),
-        description: properties["description"]["rich_text"][0]["plain_text"]
-          .as_str()
-          .ok_or(
-            anyhow!("Get `description` failed.")
-          )?
-          .into(),
-        url: properties["url"]["url"]
-          .as_str()
-          .ok_or(
-            anyhow!("Get `url` failed.")
-          )?
-          .into(),
-        icon: properties["icon"]["files"][0]["external"]["url"]
-          .as_str()
-          .ok_or(
-            anyhow!("Get `icon` failed.")
-          )?
-          .into(),
+        id: properties.id()?,
+        last_edited_time: properties.last_edited_time()?,
+        name: properties.text("name")?,
+        description: rich_text::plain_text(&description),
+        description_rich_text: description,
+        url: properties.text("url")?,
+        icon: properties.file("icon")?
       }
     )
   }
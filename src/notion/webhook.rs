@@ -0,0 +1,191 @@
+use std::{
+  collections::{HashMap, HashSet},
+  sync::OnceLock,
+  env,
+  time::Duration
+};
+
+use hmac::{Hmac, Mac};
+use serde_json::Value;
+use sha2::Sha256;
+use tokio::{sync::Mutex, time::sleep};
+use tracing::log::{debug, info, warn};
+
+use super::{
+  client::{normalize_page_id, update_types, update_page, SyncMode},
+  types::NotionDataType
+};
+
+
+pub static WEBHOOK_SECRET: OnceLock<Option<String>> = OnceLock::new();
+pub static DEBOUNCER: OnceLock<Debouncer> = OnceLock::new();
+static DEFAULT_DEBOUNCE: Duration = Duration::from_secs(2);
+
+
+/// Verification token Notion sent when the webhook subscription was created,
+/// read from `NOTION_WEBHOOK_SECRET`. Every event is signed with it.
+pub fn get_webhook_secret() -> Option<&'static str> {
+  WEBHOOK_SECRET.get_or_init(
+    || env::var("NOTION_WEBHOOK_SECRET").ok().filter(|secret| !secret.is_empty())
+  ).as_deref()
+}
+
+/// Check an `X-Notion-Signature` header, `sha256=<hex HMAC of the body>`.
+pub fn verify_signature(
+  secret: &str,
+  body: &[u8],
+  signature: &str
+) -> bool {
+  let Some(signature) = signature
+    .strip_prefix("sha256=")
+    .and_then(|signature| hex::decode(signature).ok()) else {
+    return false;
+  };
+
+  let Ok(mut mac) = Hmac::<Sha256>::new_from_slice(secret.as_bytes()) else {
+    return false;
+  };
+  mac.update(body);
+
+  mac.verify_slice(&signature).is_ok()
+}
+
+/// What a webhook event asks to refresh.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Refresh {
+  Type(NotionDataType, SyncMode),
+  Page(NotionDataType, String)
+}
+
+/// What to refresh for a webhook event. `None` for events about pages and
+/// databases this API does not serve.
+pub fn affected(event: &Value) -> Option<Refresh> {
+  let event_type: &str = event["type"].as_str()?;
+
+  let (database_id, page_id): (&str, Option<String>) = match event["entity"]["type"].as_str()? {
+    "database" => (event["entity"]["id"].as_str()?, None),
+    "page" => (
+      event["data"]["parent"]["id"].as_str()?,
+      Some(event["entity"]["id"].as_str().and_then(normalize_page_id)?)
+    ),
+    _ => return None
+  };
+  let data_type: NotionDataType = NotionDataType::from_database_id(database_id)?;
+
+  Some(
+    match (event_type, page_id) {
+      // The parent of a moved page is its new database; the old one is only
+      // cleaned up by scanning it.
+      ("page.moved", _) => Refresh::Type(data_type, SyncMode::IncrementalWithScan),
+      (_, Some(page_id)) => Refresh::Page(data_type, page_id),
+      ("database.schema_updated", None) => Refresh::Type(data_type, SyncMode::Full),
+      (_, None) => Refresh::Type(data_type, SyncMode::Incremental)
+    }
+  )
+}
+
+
+/// Refreshes collected during a burst of webhook events.
+#[derive(Debug, Default)]
+struct Pending {
+  types: HashMap<NotionDataType, SyncMode>,
+  pages: HashSet<(NotionDataType, String)>
+}
+
+/// Collects the types and pages named by a burst of webhook events and
+/// refreshes them together once the burst is over.
+pub struct Debouncer {
+  delay: Duration,
+  pending: Mutex<Option<Pending>>
+}
+
+impl Debouncer {
+  fn new() -> Debouncer {
+    Debouncer {
+      delay: env::var("WEBHOOK_DEBOUNCE_MS")
+        .ok()
+        .and_then(|delay| delay.parse::<u64>().ok())
+        .map(Duration::from_millis)
+        .unwrap_or(DEFAULT_DEBOUNCE),
+      pending: Mutex::new(None)
+    }
+  }
+
+  /// Debouncer whose delay is read from `WEBHOOK_DEBOUNCE_MS`.
+  pub fn get() -> &'static Debouncer {
+    DEBOUNCER.get_or_init(
+      Debouncer::new
+    )
+  }
+
+  /// Queue a refresh. The first event of a burst starts the delay; events
+  /// arriving before it ends are merged into the same refresh.
+  pub async fn push(
+    self: &'static Self,
+    refresh: Refresh
+  ) {
+    let mut pending = self.pending.lock().await;
+
+    let started: bool = pending.is_none();
+    let requests: &mut Pending = pending.get_or_insert_with(Pending::default);
+
+    match refresh {
+      Refresh::Type(data_type, mode) => {
+        let merged: SyncMode = match requests.types.get(&data_type) {
+          Some(queued) => (*queued).max(mode),
+          None => mode
+        };
+        requests.types.insert(data_type, merged);
+      },
+      Refresh::Page(data_type, id) => {
+        requests.pages.insert((data_type, id));
+      }
+    }
+
+    if !started {
+      return;
+    }
+
+    tokio::spawn(
+      async move {
+        sleep(self.delay).await;
+
+        let requests: Pending = self.pending
+          .lock()
+          .await
+          .take()
+          .unwrap_or_default();
+
+        self.refresh(requests).await;
+        debug!("Webhook refresh finished.");
+      }
+    );
+  }
+
+  /// Refresh the pending types, then each pending page whose type was not
+  /// refreshed as a whole.
+  async fn refresh(self: &Self, requests: Pending) {
+    if !requests.types.is_empty() {
+      let types: Vec<(NotionDataType, SyncMode)> = requests.types
+        .iter()
+        .map(|(data_type, mode)| (data_type.clone(), *mode))
+        .collect();
+
+      info!("Refreshing {:?} after webhook events.", types);
+      if let Err(error) = update_types(&types).await {
+        warn!("Webhook refresh incomplete: {:#}", error);
+      }
+    }
+
+    for (data_type, id) in requests.pages {
+      if requests.types.contains_key(&data_type) {
+        continue;
+      }
+
+      info!("Refreshing {:?} page {} after webhook events.", data_type, id);
+      if let Err(error) = update_page(&data_type, &id).await {
+        warn!("Webhook refresh of {:?} page {} failed: {:#}", data_type, id, error);
+      }
+    }
+  }
+}
@@ -0,0 +1,348 @@
+use std::cmp::Ordering;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::notion::{index::Filter, render::ContentFormat, types::NotionDataType};
+
+
+/// Most records a list request may ask for at once.
+pub static MAX_LIMIT: usize = 100;
+
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all(deserialize = "snake_case"))]
+pub enum SortOrder {
+  #[default]
+  Asc,
+  Desc
+}
+
+/// Query parameters of the list routes.
+#[derive(Debug, Default, Deserialize)]
+pub struct ListQuery {
+  /// Records per page. Every record when unset.
+  pub limit: Option<usize>,
+  #[serde(default)]
+  pub offset: usize,
+  /// Field to order by, a dotted path such as `date.start`. Records are
+  /// ordered by id when unset, and ties are broken by id.
+  pub sort: Option<String>,
+  #[serde(default)]
+  pub order: SortOrder,
+  #[serde(default)]
+  pub format: ContentFormat,
+  /// Events that end on or after this day.
+  pub from: Option<NaiveDate>,
+  /// Events that start on or before this day.
+  pub to: Option<NaiveDate>,
+  /// Articles with this tag.
+  pub tag: Option<String>,
+  /// Members of the club with this id.
+  pub club: Option<String>,
+  /// Members of the group with this id.
+  pub group: Option<String>,
+  /// Members holding this club position.
+  pub position: Option<String>,
+  /// Clubs of this school.
+  pub school: Option<String>,
+  /// Comma-separated fields to return; see [`Projection`].
+  pub fields: Option<String>,
+  /// Comma-separated relations to embed; see [`Projection`].
+  pub expand: Option<String>
+}
+
+/// Query parameters of the routes of a single record.
+#[derive(Debug, Default, Deserialize)]
+pub struct ItemQuery {
+  #[serde(default)]
+  pub format: ContentFormat,
+  pub fields: Option<String>,
+  pub expand: Option<String>
+}
+
+impl ItemQuery {
+  pub fn projection(self: &Self) -> Projection<'_> {
+    Projection::new(self.fields.as_deref(), self.expand.as_deref())
+  }
+}
+
+impl ListQuery {
+  pub fn projection(self: &Self) -> Projection<'_> {
+    Projection::new(self.fields.as_deref(), self.expand.as_deref())
+  }
+
+  /// Why the query cannot be answered for a type, if it cannot.
+  pub fn validate(self: &Self, data_type: &NotionDataType) -> Result<(), String> {
+    self.projection().validate(data_type)?;
+
+    if self.limit.is_some_and(|limit| !(1..=MAX_LIMIT).contains(&limit)) {
+      return Err(format!("`limit` must be between 1 and {MAX_LIMIT}."));
+    }
+
+    let filterable: &[&str] = filter_parameters(data_type);
+    if let Some((parameter, _)) = self
+      .parameters()
+      .into_iter()
+      .find(|(parameter, value)| value.is_some() && !filterable.contains(parameter)) {
+      return Err(format!("Cannot filter {} by `{parameter}`.", data_type.name()));
+    }
+
+    if let Some(sort) = &self.sort {
+      if !data_type.sortable_fields().contains(&sort.as_str()) {
+        return Err(
+          format!(
+            "Cannot sort {} by `{sort}`, expected one of: {}.",
+            data_type.name(),
+            data_type.sortable_fields().join(", ")
+          )
+        );
+      }
+    }
+
+    Ok(())
+  }
+
+  /// Every filter parameter with its value, if given.
+  fn parameters(self: &Self) -> [(&'static str, Option<String>); 7] {
+    [
+      ("from", self.from.map(|from| from.to_string())),
+      ("to", self.to.map(|to| to.to_string())),
+      ("tag", self.tag.clone()),
+      ("club", self.club.clone()),
+      ("group", self.group.clone()),
+      ("position", self.position.clone()),
+      ("school", self.school.clone())
+    ]
+  }
+
+  /// The given filter parameters as conditions on indexed fields.
+  pub fn filters(self: &Self) -> Vec<Filter> {
+    self
+      .parameters()
+      .into_iter()
+      .filter_map(
+        |(parameter, value)| {
+          let value: String = value?;
+
+          Some(
+            match parameter {
+              // An event overlaps the range if it ends after it starts and
+              // starts before it ends.
+              "from" => Filter::AtLeast("end", value),
+              "to" => Filter::AtMost("start", value),
+              field => Filter::Is(field, value)
+            }
+          )
+        }
+      )
+      .collect()
+  }
+
+  /// Order `records` as requested.
+  pub fn sort(self: &Self, records: &mut [Value]) {
+    let sort: &str = self.sort.as_deref().unwrap_or("id");
+
+    records.sort_by(
+      |a, b| {
+        let ordering: Ordering = compare(field(a, sort), field(b, sort))
+          .then_with(|| compare(&a["id"], &b["id"]));
+
+        match self.order {
+          SortOrder::Asc => ordering,
+          SortOrder::Desc => ordering.reverse()
+        }
+      }
+    );
+  }
+
+  /// The requested page of `records`.
+  pub fn page(self: &Self, records: Vec<Value>) -> Vec<Value> {
+    records
+      .into_iter()
+      .skip(self.offset)
+      .take(self.limit.unwrap_or(usize::MAX))
+      .collect()
+  }
+
+  /// `Link` header pointing at the previous and next pages of `total`
+  /// records, keeping every other parameter of `query`.
+  pub fn links(
+    self: &Self,
+    path: &str,
+    query: Option<&str>,
+    total: usize
+  ) -> Option<String> {
+    let limit: usize = self.limit?;
+
+    let link = |offset: usize, relation: &str| -> String {
+      let offset: String = format!("offset={offset}");
+      let parameters: String = query
+        .unwrap_or("")
+        .split('&')
+        .filter(|parameter| !parameter.is_empty() && !parameter.starts_with("offset="))
+        .chain([offset.as_str()])
+        .collect::<Vec<&str>>()
+        .join("&");
+
+      format!("<{path}?{parameters}>; rel=\"{relation}\"")
+    };
+
+    let mut links: Vec<String> = Vec::new();
+    if self.offset > 0 {
+      links.push(link(self.offset.saturating_sub(limit), "prev"));
+    }
+    let next: usize = self.offset.saturating_add(limit);
+    if next < total {
+      links.push(link(next, "next"));
+    }
+
+    (!links.is_empty()).then(|| links.join(", "))
+  }
+}
+
+/// The shape of the returned records: `fields`, the top-level fields to keep,
+/// and `expand`, the relations to embed as records. When `expand` is given,
+/// relations not named in it are returned as ids; nested relations are named
+/// by their path, such as `principal.groups`. Both default to everything.
+pub struct Projection<'a> {
+  fields: Option<Vec<&'a str>>,
+  expand: Option<Vec<&'a str>>
+}
+
+impl<'a> Projection<'a> {
+  fn new(fields: Option<&'a str>, expand: Option<&'a str>) -> Projection<'a> {
+    let split = |list: &'a str| -> Vec<&'a str> {
+      list
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .collect()
+    };
+
+    Projection {
+      fields: fields.map(split),
+      expand: expand.map(split)
+    }
+  }
+
+  /// Why the projection does not apply to a type, if it does not.
+  pub fn validate(self: &Self, data_type: &NotionDataType) -> Result<(), String> {
+    let known: Vec<String> = data_type.fields();
+    for field in self.fields.iter().flatten() {
+      if !known.iter().any(|known| known == field) {
+        return Err(format!("{} has no field `{field}`.", data_type.name()));
+      }
+    }
+
+    for path in self.expand.iter().flatten() {
+      let mut related: NotionDataType = data_type.clone();
+
+      for relation in path.split('.') {
+        related = related
+          .relations()
+          .iter()
+          .find(|(name, _)| *name == relation)
+          .map(|(_, related)| related.clone())
+          .ok_or(format!("Cannot expand `{path}`: {} has no relation `{relation}`.", related.name()))?;
+      }
+    }
+
+    Ok(())
+  }
+
+  /// Shape a serialized record of a type.
+  pub fn apply(self: &Self, data_type: &NotionDataType, record: &mut Value) {
+    if let Some(expand) = &self.expand {
+      collapse(data_type, record, expand);
+    }
+
+    if let (Some(fields), Value::Object(record)) = (&self.fields, record) {
+      record.retain(|key, _| key == "id" || fields.contains(&key.as_str()));
+    }
+  }
+}
+
+/// Replace the embedded records of every relation not under one of `expand`
+/// with their ids, descending into those that are.
+fn collapse(data_type: &NotionDataType, record: &mut Value, expand: &[&str]) {
+  for (relation, related) in data_type.relations() {
+    let nested: Vec<&str> = expand
+      .iter()
+      .filter_map(|path| path.strip_prefix(relation)?.strip_prefix('.'))
+      .collect();
+    let expanded: bool = !nested.is_empty() || expand.contains(relation);
+
+    let value: &mut Value = &mut record[*relation];
+    match value {
+      Value::Array(records) if expanded => records
+        .iter_mut()
+        .for_each(|record| collapse(related, record, &nested)),
+      Value::Object(_) if expanded => collapse(related, value, &nested),
+      Value::Array(records) => records
+        .iter_mut()
+        .for_each(|record| *record = record["id"].take()),
+      Value::Object(_) => *value = value["id"].take(),
+      _ => {}
+    }
+  }
+}
+
+/// Parameters the records of a type can be filtered by.
+fn filter_parameters(data_type: &NotionDataType) -> &'static [&'static str] {
+  match data_type {
+    NotionDataType::Event => &["from", "to"],
+    NotionDataType::Article => &["tag"],
+    NotionDataType::Member => &["club", "group", "position"],
+    NotionDataType::Club => &["school"],
+    NotionDataType::Group | NotionDataType::Sponsor => &[]
+  }
+}
+
+/// Value at a dotted path, or `null`.
+pub fn field<'a>(record: &'a Value, path: &str) -> &'a Value {
+  path.split('.').fold(record, |value, key| &value[key])
+}
+
+/// Instant a date or date-time string stands for. A date without a time
+/// counts as its start in UTC.
+fn timestamp(value: &str) -> Option<DateTime<Utc>> {
+  DateTime::parse_from_rfc3339(value)
+    .map(|time| time.with_timezone(&Utc))
+    .ok()
+    .or_else(
+      || {
+        let date: NaiveDate = NaiveDate::parse_from_str(value, "%Y-%m-%d").ok()?;
+
+        Some(date.and_hms_opt(0, 0, 0)?.and_utc())
+      }
+    )
+}
+
+/// Order JSON values: `null` first, then booleans, numbers and strings.
+/// Strings that are both dates are ordered by the time they stand for.
+fn compare(a: &Value, b: &Value) -> Ordering {
+  fn rank(value: &Value) -> u8 {
+    match value {
+      Value::Null => 0,
+      Value::Bool(_) => 1,
+      Value::Number(_) => 2,
+      Value::String(_) => 3,
+      Value::Array(_) | Value::Object(_) => 4
+    }
+  }
+
+  match (a, b) {
+    (Value::Bool(a), Value::Bool(b)) => a.cmp(b),
+    (Value::Number(a), Value::Number(b)) => a
+      .as_f64()
+      .partial_cmp(&b.as_f64())
+      .unwrap_or(Ordering::Equal),
+    (Value::String(a), Value::String(b)) => match (timestamp(a), timestamp(b)) {
+      (Some(a), Some(b)) => a.cmp(&b),
+      _ => a.cmp(b)
+    },
+    _ => rank(a).cmp(&rank(b))
+  }
+}
@@ -0,0 +1,244 @@
+use std::{
+  collections::HashMap,
+  sync::OnceLock,
+  env,
+  time::Duration
+};
+
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use serde::Serialize;
+use tokio::{sync::Mutex, time::sleep};
+use tracing::log::{debug, info, warn, error};
+
+use crate::notion::{
+  cache::CacheStorage,
+  client::{update_types, SyncMode},
+  types::NotionDataType
+};
+
+
+pub static SCHEDULER: OnceLock<Scheduler> = OnceLock::new();
+pub static MAX_CACHE_AGE: Duration = Duration::from_secs(86400);
+pub static SCAN_INTERVAL: Duration = Duration::from_secs(900);
+static MIN_FULL_SYNC_INTERVAL: Duration = Duration::from_secs(60);
+pub static EXPIRY_MARGIN: Duration = Duration::from_secs(300);
+/// Longest the scheduler sleeps between checks.
+static MAX_TICK: Duration = Duration::from_secs(60);
+/// Every run is delayed by up to this fraction of its interval.
+pub static JITTER_RATIO: f64 = 0.1;
+
+
+/// When each type is synced next, and how.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all(serialize = "snake_case"))]
+pub struct TypeSchedule {
+  /// Seconds between incremental syncs.
+  pub interval: u64,
+  pub next_run: DateTime<Utc>,
+  /// Runs at or after this time also scan for deleted pages.
+  pub next_scan: DateTime<Utc>,
+  /// Runs at or after this time fetch every row.
+  pub next_full_sync: DateTime<Utc>,
+  pub last_run: Option<DateTime<Utc>>,
+  pub running: bool
+}
+
+/// Default interval of each type: content that changes daily is polled
+/// every minute, the rest less often.
+fn default_interval(data_type: &NotionDataType) -> Duration {
+  match data_type {
+    NotionDataType::Article | NotionDataType::Event => Duration::from_secs(60),
+    NotionDataType::Member | NotionDataType::Group => Duration::from_secs(300),
+    NotionDataType::Club | NotionDataType::Sponsor => Duration::from_secs(3600)
+  }
+}
+
+/// Interval of a type, read from `SYNC_INTERVAL_<TYPE>` in seconds, e.g.
+/// `SYNC_INTERVAL_ARTICLE=60`.
+fn get_interval(data_type: &NotionDataType) -> Duration {
+  env::var(format!("SYNC_INTERVAL_{}", data_type.name().to_uppercase()))
+    .ok()
+    .and_then(|interval| interval.parse::<u64>().ok())
+    .filter(|interval| *interval > 0)
+    .map(Duration::from_secs)
+    .unwrap_or(default_interval(data_type))
+}
+
+fn jittered(interval: Duration) -> Duration {
+  interval.mul_f64(1.0 + rand::thread_rng().gen_range(0.0..=JITTER_RATIO))
+}
+
+fn after(time: DateTime<Utc>, delay: Duration) -> DateTime<Utc> {
+  time + chrono::Duration::from_std(delay).unwrap_or(chrono::Duration::zero())
+}
+
+/// Delay until the next full sync of a type: `MAX_CACHE_AGE`, or less if a
+/// Notion-hosted file url of the type expires sooner.
+pub fn full_sync_delay(data_type: &NotionDataType) -> Duration {
+  match CacheStorage::get().snapshot().expiry_time(data_type) {
+    Some(expiry_time) => (expiry_time - Utc::now())
+      .to_std()
+      .unwrap_or(Duration::ZERO)
+      .saturating_sub(EXPIRY_MARGIN)
+      .max(MIN_FULL_SYNC_INTERVAL)
+      .min(MAX_CACHE_AGE),
+    None => MAX_CACHE_AGE
+  }
+}
+
+
+/// Claim the types of `schedules` that are due at `now`, each with the mode
+/// it is synced in, and move their next run forward.
+pub fn take_due_at(
+  schedules: &mut HashMap<NotionDataType, TypeSchedule>,
+  now: DateTime<Utc>
+) -> Vec<(NotionDataType, SyncMode)> {
+  let mut due: Vec<(NotionDataType, SyncMode)> = Vec::new();
+
+  for (data_type, schedule) in schedules.iter_mut() {
+    if schedule.next_run > now {
+      continue;
+    }
+
+    schedule.next_run = after(now, jittered(Duration::from_secs(schedule.interval)));
+
+    if schedule.running {
+      warn!(
+        "Skip {:?} sync, the previous run is still in progress. Next run at {}.",
+        data_type, schedule.next_run
+      );
+      continue;
+    }
+
+    let mode: SyncMode = if now >= schedule.next_full_sync {
+      SyncMode::Full
+    } else if now >= schedule.next_scan {
+      SyncMode::IncrementalWithScan
+    } else {
+      SyncMode::Incremental
+    };
+
+    if mode != SyncMode::Incremental {
+      schedule.next_scan = after(now, SCAN_INTERVAL);
+    }
+    schedule.running = true;
+    schedule.last_run = Some(now);
+
+    due.push((data_type.clone(), mode));
+  }
+
+  due
+}
+
+/// Runs the sync of each type on its own interval. Types that fall due
+/// together are synced together; a type whose previous run has not finished
+/// is skipped until its next turn.
+pub struct Scheduler {
+  schedules: Mutex<HashMap<NotionDataType, TypeSchedule>>
+}
+
+impl Scheduler {
+  fn new() -> Scheduler {
+    let now: DateTime<Utc> = Utc::now();
+
+    Scheduler {
+      schedules: Mutex::new(
+        NotionDataType::iterator()
+          .map(
+            |data_type| {
+              let schedule: TypeSchedule = TypeSchedule {
+                interval: get_interval(&data_type).as_secs(),
+                next_run: now,
+                next_scan: after(now, SCAN_INTERVAL),
+                next_full_sync: now,
+                last_run: None,
+                running: false
+              };
+
+              (data_type, schedule)
+            }
+          )
+          .collect()
+      )
+    }
+  }
+
+  pub fn get() -> &'static Scheduler {
+    SCHEDULER.get_or_init(
+      Scheduler::new
+    )
+  }
+
+  pub async fn request_all(self: &Self) -> HashMap<NotionDataType, TypeSchedule> {
+    self.schedules.lock().await.clone()
+  }
+
+  /// Claim the types that are due, and move their next run forward.
+  async fn take_due(self: &Self) -> Vec<(NotionDataType, SyncMode)> {
+    take_due_at(&mut *self.schedules.lock().await, Utc::now())
+  }
+
+  async fn finish(self: &Self, runs: &[(NotionDataType, SyncMode)]) {
+    let mut schedules = self.schedules.lock().await;
+
+    for (data_type, mode) in runs {
+      let Some(schedule) = schedules.get_mut(data_type) else {
+        continue;
+      };
+
+      schedule.running = false;
+      if *mode == SyncMode::Full {
+        schedule.next_full_sync = after(Utc::now(), full_sync_delay(data_type));
+      }
+
+      debug!(
+        "Next {:?} sync at {}, full sync at {}.",
+        data_type, schedule.next_run, schedule.next_full_sync
+      );
+    }
+  }
+
+  async fn time_until_next_run(self: &Self) -> Duration {
+    self.schedules
+      .lock()
+      .await
+      .values()
+      .map(|schedule| schedule.next_run)
+      .min()
+      .and_then(|next_run| (next_run - Utc::now()).to_std().ok())
+      .unwrap_or(Duration::ZERO)
+      .min(MAX_TICK)
+  }
+
+  /// Run forever, syncing each type as it falls due.
+  pub async fn run(self: &'static Self) {
+    for (data_type, schedule) in self.request_all().await {
+      info!("Sync {:?} every {} seconds.", data_type, schedule.interval);
+    }
+
+    loop {
+      let due: Vec<(NotionDataType, SyncMode)> = self.take_due().await;
+
+      if !due.is_empty() {
+        debug!("Updating cache: {:?}", due);
+
+        tokio::spawn(
+          async move {
+            let runs: Vec<(NotionDataType, SyncMode)> = due.clone();
+
+            // Run in its own task so a panic still releases the types.
+            match tokio::spawn(async move { update_types(&due).await }).await {
+              Ok(Ok(())) => {},
+              Ok(Err(error)) => warn!("Sync of {:?} incomplete: {:#}", runs, error),
+              Err(error) => error!("Sync of {:?} failed: {}", runs, error)
+            }
+            self.finish(&runs).await;
+          }
+        );
+      }
+
+      sleep(self.time_until_next_run().await.max(Duration::from_secs(1))).await;
+    }
+  }
+}
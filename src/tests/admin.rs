@@ -0,0 +1,220 @@
+use std::time::Duration;
+
+use axum::{
+  body::Body,
+  http::{Request, StatusCode, header}
+};
+use serde_json::Value;
+use tokio::time::{sleep, Instant};
+
+use crate::notion::{client::SyncMode, types::NotionDataType};
+
+use super::{
+  run,
+  send,
+  send_admin,
+  get_json,
+  get_admin_json,
+  ids,
+  mock_notion::MockState,
+  pages,
+  sync::{update_all, workspace}
+};
+
+
+/// Poll a job until it finishes.
+async fn wait_for_job(id: &str) -> Value {
+  let deadline: Instant = Instant::now() + Duration::from_secs(10);
+
+  loop {
+    let job: Value = get_admin_json(&format!("/admin/jobs/{id}")).await;
+    if job["status"] != "queued" && job["status"] != "running" {
+      return job;
+    }
+
+    assert!(Instant::now() < deadline, "job {id} did not finish");
+    sleep(Duration::from_millis(20)).await;
+  }
+}
+
+
+#[test]
+fn admin_routes_require_a_token() {
+  run(
+    |_| async move {
+      for (method, path) in [
+        ("GET", "/admin/sync-report"),
+        ("GET", "/admin/schedule"),
+        ("POST", "/admin/refresh"),
+        ("POST", "/admin/refresh/clubs"),
+        ("POST", "/admin/refresh/clubs/club-1"),
+        ("GET", "/admin/jobs/missing")
+      ] {
+        for authorization in [None, Some("Bearer wrong-token"), Some("admin-token")] {
+          let mut request = Request::builder().method(method).uri(path);
+          if let Some(authorization) = authorization {
+            request = request.header(header::AUTHORIZATION, authorization);
+          }
+
+          let (status, headers, _) = send(request.body(Body::empty()).unwrap()).await;
+          assert_eq!(status, StatusCode::UNAUTHORIZED, "{method} {path} with {authorization:?}");
+          assert_eq!(headers[header::WWW_AUTHENTICATE], "Bearer");
+        }
+      }
+
+      let (status, _, _) = send(
+        Request::get("/admin/schedule")
+          .header(header::AUTHORIZATION, "Bearer old-token")
+          .body(Body::empty())
+          .unwrap()
+      ).await;
+      assert_eq!(status, StatusCode::OK);
+
+      assert_eq!(send_admin("GET", "/admin/jobs/missing").await.0, StatusCode::NOT_FOUND);
+      assert_eq!(send_admin("POST", "/admin/refresh/unknown").await.0, StatusCode::NOT_FOUND);
+    }
+  );
+}
+
+#[test]
+fn refresh_jobs_are_deduplicated_and_polled_until_done() {
+  run(
+    |mock_notion| async move {
+      let mut state: MockState = workspace(mock_notion);
+      mock_notion.reset(workspace(mock_notion));
+      update_all(SyncMode::Full).await;
+
+      state.databases
+        .get_mut(&pages::database_id(&NotionDataType::Club))
+        .unwrap()
+        .push(pages::club("club-2", "New club", &mock_notion.file_url("image.png")));
+      mock_notion.reset(state);
+
+      let (status, headers, bytes) = send_admin("POST", "/admin/refresh/clubs").await;
+      assert_eq!(status, StatusCode::ACCEPTED);
+      let job: Value = serde_json::from_slice(&bytes).unwrap();
+      let id: &str = job["id"].as_str().unwrap();
+      assert_eq!(headers[header::LOCATION], format!("/admin/jobs/{id}"));
+      assert_eq!(job["target"]["kind"], "type");
+      assert_eq!(job["target"]["data_type"], "club");
+
+      let (status, _, bytes) = send_admin("POST", "/admin/refresh/club").await;
+      assert_eq!(status, StatusCode::ACCEPTED);
+      let duplicate: Value = serde_json::from_slice(&bytes).unwrap();
+      assert_eq!(duplicate["id"], id, "a pending refresh of the same type was not reused");
+
+      let job: Value = wait_for_job(id).await;
+      assert_eq!(job["status"], "succeeded");
+      assert!(job["finished_at"].is_string());
+      assert_eq!(ids(&get_json("/clubs").await), ["club-1", "club-2"]);
+
+      let (_, _, bytes) = send_admin("POST", "/admin/refresh/clubs").await;
+      let next: Value = serde_json::from_slice(&bytes).unwrap();
+      assert_ne!(next["id"], id, "a finished job was reused");
+      wait_for_job(next["id"].as_str().unwrap()).await;
+    }
+  );
+}
+
+#[test]
+fn page_refresh_fetches_only_that_page() {
+  run(
+    |mock_notion| async move {
+      let edited: &str = "0a9b1e2f-3c4d-4e5f-8a6b-7c8d9e0f1a2b";
+      let created: &str = "1b2c3d4e-5f6a-4b7c-8d9e-0f1a2b3c4d5e";
+      let missing: &str = "2c3d4e5f-6a7b-4c8d-9e0f-1a2b3c4d5e6f";
+      let image_url: String = mock_notion.file_url("image.png");
+
+      let mut state: MockState = workspace(mock_notion);
+      state.databases
+        .get_mut(&pages::database_id(&NotionDataType::Event))
+        .unwrap()
+        .push(pages::event(edited, "Workshop", &[], &image_url));
+      mock_notion.reset(state);
+      update_all(SyncMode::Full).await;
+
+      let mut state: MockState = workspace(mock_notion);
+      let events: &mut Vec<Value> = state.databases
+        .get_mut(&pages::database_id(&NotionDataType::Event))
+        .unwrap();
+      events.push(pages::event(edited, "Summer workshop", &["member-2"], &image_url));
+      events.push(pages::event(created, "Hackathon", &[], &image_url));
+      mock_notion.reset(state);
+
+      // Ids as in a page url, without dashes, match the cached ones.
+      for id in [edited.replace('-', ""), created.replace('-', "").to_uppercase(), missing.into()] {
+        let (status, _, bytes) = send_admin("POST", &format!("/admin/refresh/events/{id}")).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        let job: Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(job["target"]["kind"], "page");
+        assert_eq!(wait_for_job(job["id"].as_str().unwrap()).await["status"], "succeeded");
+      }
+
+      let event: Value = get_json(&format!("/events/{edited}")).await;
+      assert_eq!(event["name"], "Summer workshop");
+      assert_eq!(ids(&event["principal"]), ["member-2"]);
+      assert_eq!(ids(&get_json("/events").await), [edited, created, "event-1"]);
+      assert_eq!(
+        mock_notion.requests(),
+        [edited, created, missing].map(|id| format!("GET /v1/pages/{id}"))
+      );
+
+      for id in ["event-1", "..%2Fdatabases%2Fevent-database", "0a9b1e2f-3c4d-4e5f-8a6b-7c8d9e0f1a2b%3Fx"] {
+        let (status, _, _) = send_admin("POST", &format!("/admin/refresh/events/{id}")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "refresh of {id}");
+      }
+      assert_eq!(mock_notion.requests().len(), 3);
+
+      let mut state: MockState = workspace(mock_notion);
+      let events: &mut Vec<Value> = state.databases
+        .get_mut(&pages::database_id(&NotionDataType::Event))
+        .unwrap();
+      events.push(pages::event(edited, "Summer workshop", &["member-2"], &image_url));
+      pages::archive(events.last_mut().unwrap());
+      mock_notion.reset(state);
+
+      let (_, _, bytes) = send_admin("POST", &format!("/admin/refresh/events/{}", edited.replace('-', ""))).await;
+      let job: Value = serde_json::from_slice(&bytes).unwrap();
+      wait_for_job(job["id"].as_str().unwrap()).await;
+      assert_eq!(ids(&get_json("/events").await), [created, "event-1"]);
+    }
+  );
+}
+
+#[test]
+fn failed_refresh_reports_the_error() {
+  run(
+    |mock_notion| async move {
+      let mut state: MockState = workspace(mock_notion);
+      state.failing.insert(pages::database_id(&NotionDataType::Sponsor));
+      mock_notion.reset(state);
+
+      let (status, _, bytes) = send_admin("POST", "/admin/refresh/sponsors").await;
+      assert_eq!(status, StatusCode::ACCEPTED);
+      let job: Value = serde_json::from_slice(&bytes).unwrap();
+
+      let job: Value = wait_for_job(job["id"].as_str().unwrap()).await;
+      assert_eq!(job["status"], "failed");
+      assert!(job["error"].as_str().unwrap().starts_with("Update failed for sponsor"));
+    }
+  );
+}
+
+#[test]
+fn no_cache_requests_do_not_reach_notion() {
+  run(
+    |mock_notion| async move {
+      mock_notion.reset(workspace(mock_notion));
+
+      let (status, _, _) = send(
+        Request::get("/members")
+          .header(header::CACHE_CONTROL, "no-cache")
+          .body(Body::empty())
+          .unwrap()
+      ).await;
+
+      assert_eq!(status, StatusCode::OK);
+      assert_eq!(mock_notion.requests(), Vec::<String>::new());
+    }
+  );
+}
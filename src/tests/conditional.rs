@@ -0,0 +1,134 @@
+use axum::{
+  body::Body,
+  http::{HeaderMap, Request, StatusCode, header}
+};
+use hyper::body::Bytes;
+use serde_json::Value;
+
+use crate::notion::{
+  cache::{CacheStorage, Watermarks},
+  client::SyncMode,
+  relations::Dataset,
+  types::NotionDataType
+};
+
+use super::{
+  run,
+  send,
+  get,
+  mock_notion::MockState,
+  pages,
+  sync::{update_all, workspace}
+};
+
+
+async fn get_with(path: &str, name: header::HeaderName, value: &str) -> (StatusCode, HeaderMap, Bytes) {
+  send(
+    Request::get(path)
+      .header(name, value)
+      .body(Body::empty())
+      .unwrap()
+  ).await
+}
+
+
+/// The first group, edited after the rest of the workspace.
+fn groups_edited() -> Value {
+  let mut group: Value = pages::group("group-1", "Core team", &["member-1", "member-2"]);
+  pages::edit(&mut group, "2023-08-03T00:00:00.000Z");
+
+  group
+}
+
+
+#[test]
+fn conditional_requests_are_answered_with_not_modified() {
+  run(
+    |mock_notion| async move {
+      // Collections count as changed whenever earlier tests left other
+      // records, so start from an empty cache.
+      CacheStorage::get().publish(Dataset::new(), Watermarks::new());
+      mock_notion.reset(workspace(mock_notion));
+      update_all(SyncMode::Full).await;
+
+      for path in ["/events", "/events/event-1", "/articles", "/articles/article-1?format=html"] {
+        let (status, headers, _) = get(path).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(headers[header::CACHE_CONTROL], "public, max-age=60, stale-while-revalidate=600");
+        assert_eq!(headers[header::LAST_MODIFIED], "Wed, 02 Aug 2023 00:00:00 GMT");
+        let etag: &str = headers[header::ETAG].to_str().unwrap();
+
+        let (status, not_modified, bytes) = get_with(path, header::IF_NONE_MATCH, etag).await;
+        assert_eq!(status, StatusCode::NOT_MODIFIED, "GET {path}");
+        assert!(bytes.is_empty());
+        assert_eq!(not_modified[header::ETAG], etag);
+        assert_eq!(not_modified["X-Data-Stale"], "false");
+
+        let (status, _, _) = get_with(path, header::IF_NONE_MATCH, &format!("\"other\", W/{etag}")).await;
+        assert_eq!(status, StatusCode::NOT_MODIFIED);
+        let (status, _, _) = get_with(path, header::IF_NONE_MATCH, "\"other\"").await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, _, _) = get_with(path, header::IF_MODIFIED_SINCE, "Wed, 02 Aug 2023 00:00:00 GMT").await;
+        assert_eq!(status, StatusCode::NOT_MODIFIED);
+        let (status, _, _) = get_with(path, header::IF_MODIFIED_SINCE, "Tue, 01 Aug 2023 00:00:00 GMT").await;
+        assert_eq!(status, StatusCode::OK);
+      }
+
+      let blocks: HeaderMap = get("/articles/article-1").await.1;
+      let html: HeaderMap = get("/articles/article-1?format=html").await.1;
+      assert_ne!(blocks[header::ETAG], html[header::ETAG]);
+
+      let events: HeaderMap = get("/events").await.1;
+      let event: HeaderMap = get("/events/event-1").await.1;
+      let member: HeaderMap = get("/members/member-1").await.1;
+
+      // An edit to a group changes the members that embed it.
+      let mut state: MockState = workspace(mock_notion);
+      let groups: &mut Vec<Value> = state.databases
+        .get_mut(&pages::database_id(&NotionDataType::Group))
+        .unwrap();
+      groups[0] = groups_edited();
+      mock_notion.reset(state);
+      update_all(SyncMode::Incremental).await;
+
+      let (status, headers, _) = get_with(
+        "/members/member-1",
+        header::IF_NONE_MATCH,
+        member[header::ETAG].to_str().unwrap()
+      ).await;
+      assert_eq!(status, StatusCode::OK);
+      assert_ne!(headers[header::ETAG], member[header::ETAG]);
+      assert_eq!(headers[header::LAST_MODIFIED], "Thu, 03 Aug 2023 00:00:00 GMT");
+
+      // Events embed members with their groups too.
+      assert_ne!(get("/events/event-1").await.1[header::ETAG], event[header::ETAG]);
+      assert_ne!(get("/events").await.1[header::ETAG], events[header::ETAG]);
+      let (status, _, _) = get_with("/clubs", header::IF_NONE_MATCH, "*").await;
+      assert_eq!(status, StatusCode::NOT_MODIFIED);
+      assert_eq!(get("/events/missing").await.1.get(header::ETAG), None);
+
+      // Removing a record changes the collection without a newer edit.
+      let members: HeaderMap = get("/members").await.1;
+      assert_eq!(members[header::LAST_MODIFIED], "Thu, 03 Aug 2023 00:00:00 GMT");
+      let (status, _, _) = get_with("/members", header::IF_MODIFIED_SINCE, "Thu, 03 Aug 2023 00:00:00 GMT").await;
+      assert_eq!(status, StatusCode::NOT_MODIFIED);
+
+      let mut state: MockState = workspace(mock_notion);
+      state.databases
+        .get_mut(&pages::database_id(&NotionDataType::Group))
+        .unwrap()[0] = groups_edited();
+      state.databases
+        .get_mut(&pages::database_id(&NotionDataType::Member))
+        .unwrap()
+        .retain(|page| page["id"] != "member-2");
+      mock_notion.reset(state);
+      update_all(SyncMode::Full).await;
+
+      let (status, headers, _) = get_with("/members", header::IF_MODIFIED_SINCE, "Thu, 03 Aug 2023 00:00:00 GMT").await;
+      assert_eq!(status, StatusCode::OK);
+      assert_ne!(headers[header::LAST_MODIFIED], members[header::LAST_MODIFIED]);
+      assert_ne!(headers[header::ETAG], members[header::ETAG]);
+    }
+  );
+}
@@ -0,0 +1,78 @@
+use std::path::PathBuf;
+
+use serde_json::Value;
+
+use crate::notion::{
+  client::{NotionApi, SyncMode, update_page, update_types},
+  source::{self, FixtureSource},
+  types::NotionDataType
+};
+
+use super::{
+  run,
+  get_json,
+  get_admin_json,
+  ids,
+  mock_notion::MockState
+};
+
+
+/// Serves Notion responses from the checked-in fixtures until dropped.
+struct Fixtures;
+
+impl Fixtures {
+  fn install() -> Fixtures {
+    source::replace(
+      Box::new(
+        FixtureSource::new(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/tests/fixtures"))
+      )
+    );
+
+    Fixtures
+  }
+}
+
+impl Drop for Fixtures {
+  fn drop(self: &mut Self) {
+    source::replace(Box::new(NotionApi));
+  }
+}
+
+
+#[test]
+fn syncs_from_recorded_fixtures() {
+  run(
+    |mock_notion| async move {
+      let club_1: &str = "6a7b8c9d-0e1f-4a2b-8c3d-4e5f6a7b8c9d";
+      let club_2: &str = "7b8c9d0e-1f2a-4b3c-8d4e-5f6a7b8c9d0e";
+      mock_notion.reset(MockState::default());
+      let _fixtures: Fixtures = Fixtures::install();
+
+      update_types(
+        &[
+          (NotionDataType::Club, SyncMode::Full),
+          (NotionDataType::Sponsor, SyncMode::Full),
+          (NotionDataType::Article, SyncMode::Full)
+        ]
+      ).await.unwrap();
+
+      assert_eq!(ids(&get_json("/clubs").await), [club_1, club_2]);
+      assert_eq!(ids(&get_json("/sponsors").await), ["sponsor-1"]);
+      let html: Value = get_json("/articles/article-1?format=html").await;
+      assert_eq!(html["content"], "<h2>Welcome</h2><p>Recorded</p>");
+      assert_eq!(get_admin_json("/admin/sync-report").await["club"]["parsed"], 2);
+
+      // Refreshed by the ids in their page urls, without dashes.
+      update_page(&NotionDataType::Club, "7b8c9d0e1f2a4b3c8d4e5f6a7b8c9d0e").await.unwrap();
+      assert_eq!(ids(&get_json("/clubs").await), [club_1, club_2]);
+      assert_eq!(get_json(&format!("/clubs/{club_2}")).await["name"], "Robotics club");
+
+      update_page(&NotionDataType::Club, "6A7B8C9D0E1F4A2B8C3D4E5F6A7B8C9D").await.unwrap();
+      assert_eq!(ids(&get_json("/clubs").await), [club_2]);
+
+      assert!(update_page(&NotionDataType::Club, "../club/query").await.is_err());
+
+      assert_eq!(mock_notion.requests(), Vec::<String>::new());
+    }
+  );
+}
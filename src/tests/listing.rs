@@ -0,0 +1,257 @@
+use axum::http::{StatusCode, header};
+use serde_json::{Value, json};
+
+use crate::notion::{client::SyncMode, types::NotionDataType};
+
+use super::{
+  run,
+  get,
+  mock_notion::MockState,
+  pages,
+  sync::{update_all, workspace}
+};
+
+
+/// Ids in response order.
+fn ordered_ids(bytes: &[u8]) -> Vec<String> {
+  serde_json::from_slice::<Value>(bytes)
+    .unwrap()
+    .as_array()
+    .unwrap()
+    .iter()
+    .map(|record| record["id"].as_str().unwrap().into())
+    .collect()
+}
+
+
+#[test]
+fn lists_are_sorted_and_paginated() {
+  run(
+    |mock_notion| async move {
+      let mut state: MockState = workspace(mock_notion);
+      let image_url: String = mock_notion.file_url("image.png");
+      let events: &mut Vec<Value> = state.databases
+        .get_mut(&pages::database_id(&NotionDataType::Event))
+        .unwrap();
+      for (id, start) in [("event-2", "2023-09-01"), ("event-3", "2023-07-01"), ("event-4", "2023-08-20")] {
+        let mut event: Value = pages::event(id, id, &[], &image_url);
+        event["properties"]["date"]["date"]["start"] = start.into();
+        events.push(event);
+      }
+      mock_notion.reset(state);
+      update_all(SyncMode::Full).await;
+
+      let (status, headers, bytes) = get("/events").await;
+      assert_eq!(status, StatusCode::OK);
+      assert_eq!(ordered_ids(&bytes), ["event-1", "event-2", "event-3", "event-4"]);
+      assert_eq!(headers["X-Total-Count"], "4");
+      assert!(headers.get(header::LINK).is_none());
+
+      let (_, _, bytes) = get("/events?sort=date.start").await;
+      assert_eq!(ordered_ids(&bytes), ["event-3", "event-1", "event-4", "event-2"]);
+
+      let (_, headers, bytes) = get("/events?sort=date.start&order=desc&limit=2").await;
+      assert_eq!(ordered_ids(&bytes), ["event-2", "event-4"]);
+      assert_eq!(headers["X-Total-Count"], "4");
+      assert_eq!(
+        headers[header::LINK],
+        "</events?sort=date.start&order=desc&limit=2&offset=2>; rel=\"next\""
+      );
+
+      let (_, headers, bytes) = get("/events?sort=date.start&order=desc&limit=2&offset=1").await;
+      assert_eq!(ordered_ids(&bytes), ["event-4", "event-1"]);
+      assert_eq!(
+        headers[header::LINK],
+        "</events?sort=date.start&order=desc&limit=2&offset=0>; rel=\"prev\", \
+        </events?sort=date.start&order=desc&limit=2&offset=3>; rel=\"next\""
+      );
+
+      let (_, headers, bytes) = get("/events?limit=2&offset=3").await;
+      assert_eq!(ordered_ids(&bytes), ["event-4"]);
+      assert_eq!(headers[header::LINK], "</events?limit=2&offset=1>; rel=\"prev\"");
+
+      let (_, _, bytes) = get("/events?offset=10").await;
+      assert_eq!(ordered_ids(&bytes), Vec::<String>::new());
+
+      // Pages of the same collection are cached separately.
+      assert_ne!(
+        get("/events?limit=2").await.1[header::ETAG],
+        get("/events?limit=2&offset=2").await.1[header::ETAG]
+      );
+
+      let (_, _, bytes) = get("/members?sort=name&order=desc").await;
+      assert_eq!(ordered_ids(&bytes), ["member-3", "member-2", "member-1"]);
+
+      for path in [
+        "/events?sort=description",
+        "/events?order=sideways",
+        "/events?limit=0",
+        "/events?limit=101",
+        "/members?offset=-1"
+      ] {
+        assert_eq!(get(path).await.0, StatusCode::BAD_REQUEST, "GET {path}");
+      }
+
+      let (status, headers, bytes) = get(&format!("/events?limit=2&offset={}", usize::MAX)).await;
+      assert_eq!(status, StatusCode::OK);
+      assert_eq!(ordered_ids(&bytes), Vec::<String>::new());
+      assert_eq!(
+        headers[header::LINK],
+        format!("</events?limit=2&offset={}>; rel=\"prev\"", usize::MAX - 2)
+      );
+
+      // Dates with a time sort by the instant they stand for, not as text:
+      // this one is early on 10 August in UTC, after event-1 starts.
+      let mut state: MockState = workspace(mock_notion);
+      let events: &mut Vec<Value> = state.databases
+        .get_mut(&pages::database_id(&NotionDataType::Event))
+        .unwrap();
+      let mut event: Value = pages::event("event-5", "event-5", &[], &image_url);
+      event["properties"]["date"]["date"]["start"] = "2023-08-09T23:00:00.000-02:00".into();
+      events.push(event);
+      mock_notion.reset(state);
+      update_all(SyncMode::Full).await;
+
+      let (_, _, bytes) = get("/events?sort=date.start").await;
+      assert_eq!(ordered_ids(&bytes), ["event-1", "event-5"]);
+    }
+  );
+}
+
+#[test]
+fn lists_are_filtered_by_indexed_fields() {
+  run(
+    |mock_notion| async move {
+      let mut state: MockState = workspace(mock_notion);
+      let image_url: String = mock_notion.file_url("image.png");
+
+      let events: &mut Vec<Value> = state.databases
+        .get_mut(&pages::database_id(&NotionDataType::Event))
+        .unwrap();
+      for (id, start, end) in [
+        ("event-2", "2023-09-01T09:00:00.000+08:00", "2023-09-01T17:00:00.000+08:00"),
+        ("event-3", "2023-07-01", "2023-07-02")
+      ] {
+        let mut event: Value = pages::event(id, id, &[], &image_url);
+        event["properties"]["date"]["date"]["start"] = start.into();
+        event["properties"]["date"]["date"]["end"] = end.into();
+        events.push(event);
+      }
+      let mut event: Value = pages::event("event-4", "event-4", &[], &image_url);
+      event["properties"]["date"]["date"]["start"] = "2023-10-05".into();
+      event["properties"]["date"]["date"]["end"] = Value::Null;
+      events.push(event);
+
+      let members: &mut Vec<Value> = state.databases
+        .get_mut(&pages::database_id(&NotionDataType::Member))
+        .unwrap();
+      members[1]["properties"]["club_positions"]["multi_select"] = json!([{"name": "Lead"}]);
+
+      let mut article: Value = pages::article("article-2", "Recap");
+      article["properties"]["tags"]["multi_select"] = json!([{"name": "news"}, {"name": "camp"}]);
+      state.databases
+        .get_mut(&pages::database_id(&NotionDataType::Article))
+        .unwrap()
+        .push(article);
+
+      mock_notion.reset(state);
+      update_all(SyncMode::Full).await;
+
+      for (path, expected) in [
+        ("/events?from=2023-08-11", vec!["event-1", "event-2", "event-4"]),
+        ("/events?from=2023-08-13", vec!["event-2", "event-4"]),
+        ("/events?to=2023-08-10", vec!["event-1", "event-3"]),
+        ("/events?from=2023-07-02&to=2023-08-01", vec!["event-3"]),
+        ("/events?from=2023-09-01&to=2023-09-01", vec!["event-2"]),
+        ("/events?from=2023-10-05&to=2023-10-05", vec!["event-4"]),
+        ("/events?from=2023-10-06", vec![]),
+        ("/events?to=2023-10-04", vec!["event-1", "event-2", "event-3"]),
+        ("/events?from=2024-01-01", vec![]),
+        ("/articles?tag=news", vec!["article-1", "article-2"]),
+        ("/articles?tag=camp", vec!["article-2"]),
+        ("/articles?tag=missing", vec![]),
+        ("/members?club=club-1", vec!["member-1", "member-3"]),
+        ("/members?group=group-1", vec!["member-1", "member-2"]),
+        ("/members?club=club-1&group=group-1", vec!["member-1"]),
+        ("/members?position=Lead", vec!["member-2"]),
+        ("/members?position=Member&sort=name&order=desc", vec!["member-3", "member-1"]),
+        ("/clubs?school=SCAICT%20High", vec!["club-1"]),
+        ("/clubs?school=Other", vec![])
+      ] {
+        let (status, headers, bytes) = get(path).await;
+        assert_eq!(status, StatusCode::OK, "GET {path}");
+        assert_eq!(ordered_ids(&bytes), expected, "GET {path}");
+        assert_eq!(headers["X-Total-Count"], expected.len().to_string().as_str());
+      }
+
+      let (_, headers, bytes) = get("/members?club=club-1&limit=1").await;
+      assert_eq!(ordered_ids(&bytes), ["member-1"]);
+      assert_eq!(headers["X-Total-Count"], "2");
+      assert_eq!(headers[header::LINK], "</members?club=club-1&limit=1&offset=1>; rel=\"next\"");
+
+      for path in ["/events?from=tomorrow", "/events?tag=news", "/groups?club=club-1", "/sponsors?school=x"] {
+        assert_eq!(get(path).await.0, StatusCode::BAD_REQUEST, "GET {path}");
+      }
+    }
+  );
+}
+
+#[test]
+fn records_are_shaped_by_fields_and_expand() {
+  run(
+    |mock_notion| async move {
+      mock_notion.reset(workspace(mock_notion));
+      update_all(SyncMode::Full).await;
+
+      let (status, _, bytes) = get("/members/member-1?fields=name,avatar").await;
+      assert_eq!(status, StatusCode::OK);
+      let member: Value = serde_json::from_slice(&bytes).unwrap();
+      let mut keys: Vec<&String> = member.as_object().unwrap().keys().collect();
+      keys.sort();
+      assert_eq!(keys, ["avatar", "id", "name"]);
+
+      // Relations are embedded unless `expand` leaves them out.
+      let (_, _, bytes) = get("/members/member-1").await;
+      let member: Value = serde_json::from_slice(&bytes).unwrap();
+      assert_eq!(member["club"]["name"], "SCAICT");
+
+      let (_, _, bytes) = get("/members/member-1?expand=").await;
+      let member: Value = serde_json::from_slice(&bytes).unwrap();
+      assert_eq!(member["club"], "club-1");
+      assert_eq!(member["groups"], json!(["group-1"]));
+
+      let (_, _, bytes) = get("/members?expand=club&fields=club,groups").await;
+      let members: Value = serde_json::from_slice(&bytes).unwrap();
+      assert_eq!(members[0]["club"]["name"], "SCAICT");
+      assert_eq!(members[0]["groups"], json!(["group-1"]));
+      assert_eq!(members[1]["club"], Value::Null);
+      assert!(members[0].get("name").is_none());
+
+      let (_, _, bytes) = get("/events/event-1?expand=principal").await;
+      let event: Value = serde_json::from_slice(&bytes).unwrap();
+      assert_eq!(event["principal"][0]["name"], "Alice");
+      assert_eq!(event["principal"][0]["groups"], json!(["group-1"]));
+
+      let (_, _, bytes) = get("/events/event-1?expand=principal.groups").await;
+      let event: Value = serde_json::from_slice(&bytes).unwrap();
+      assert_eq!(event["principal"][0]["groups"][0]["name"], "Core");
+      assert_eq!(event["principal"][0]["club"], "club-1");
+
+      // Each shape is cached separately.
+      assert_ne!(
+        get("/events/event-1").await.1[header::ETAG],
+        get("/events/event-1?expand=principal").await.1[header::ETAG]
+      );
+
+      for path in [
+        "/members?fields=email",
+        "/members/member-1?fields=name,email",
+        "/members?expand=principal",
+        "/events/event-1?expand=principal.school",
+        "/clubs/club-1?expand=members"
+      ] {
+        assert_eq!(get(path).await.0, StatusCode::BAD_REQUEST, "GET {path}");
+      }
+    }
+  );
+}
@@ -0,0 +1,336 @@
+use std::{
+  collections::{HashMap, HashSet},
+  net::{SocketAddr, TcpListener},
+  sync::{Arc, Mutex},
+  time::Duration
+};
+
+use axum::{
+  Router,
+  Json,
+  routing::{get, post},
+  extract::{Path, Query, RawQuery, State},
+  http::{HeaderMap, StatusCode, header},
+  response::{IntoResponse, Response}
+};
+use serde::Deserialize;
+use serde_json::{Map, Value, json};
+
+use super::INTEGRATION_SECRET;
+
+
+/// What the mock server answers with. Tests replace it with
+/// [`MockNotion::reset`] before syncing.
+#[derive(Debug, Default)]
+pub struct MockState {
+  /// Pages of each database, by database id. Pages with `archived` set are
+  /// only returned when retrieved on their own.
+  pub databases: HashMap<String, Vec<Value>>,
+  /// Child blocks of each page or block, by id.
+  pub blocks: HashMap<String, Vec<Value>>,
+  /// Files served from `/files/<name>`, with their content type. A file
+  /// listed as `<name>?<query>` is served only with that query.
+  pub files: HashMap<String, (String, Vec<u8>)>,
+  /// Number of upcoming requests answered with `429 Too Many Requests`.
+  pub rate_limited: usize,
+  /// Databases whose queries, and blocks whose children, always fail with
+  /// `500 Internal Server Error`.
+  pub failing: HashSet<String>,
+  /// Databases whose queries are never answered.
+  pub stalled: HashSet<String>,
+  /// Every request received, as `<method> <path>`.
+  pub requests: Vec<String>
+}
+
+pub struct MockNotion {
+  address: SocketAddr,
+  state: Arc<Mutex<MockState>>
+}
+
+#[derive(Deserialize)]
+struct ChildrenQuery {
+  page_size: Option<usize>,
+  start_cursor: Option<String>
+}
+
+type SharedState = Arc<Mutex<MockState>>;
+
+
+impl MockNotion {
+  pub async fn start() -> MockNotion {
+    let state: SharedState = Arc::default();
+
+    let app: Router = Router::new()
+      .route("/v1/databases/:id", get(retrieve_database))
+      .route("/v1/databases/:id/query", post(query_database))
+      .route("/v1/blocks/:id/children", get(block_children))
+      .route("/v1/pages/:id", get(retrieve_page))
+      .route("/files/:name", get(file))
+      .with_state(state.clone());
+
+    let listener: TcpListener = TcpListener::bind("127.0.0.1:0").expect("Bind mock Notion failed.");
+    let address: SocketAddr = listener.local_addr().unwrap();
+
+    tokio::spawn(
+      axum::Server::from_tcp(listener)
+        .unwrap()
+        .serve(app.into_make_service())
+    );
+
+    MockNotion { address, state }
+  }
+
+  pub fn url(self: &Self) -> String {
+    format!("http://{}", self.address)
+  }
+
+  pub fn file_url(self: &Self, name: &str) -> String {
+    format!("{}/files/{name}", self.url())
+  }
+
+  pub fn reset(self: &Self, state: MockState) {
+    *self.state.lock().unwrap() = state;
+  }
+
+  pub fn requests(self: &Self) -> Vec<String> {
+    self.state.lock().unwrap().requests.clone()
+  }
+}
+
+
+/// Log the request and decide whether it is answered normally.
+fn intercept(
+  state: &SharedState,
+  headers: &HeaderMap,
+  request: String
+) -> Option<Response> {
+  let mut state = state.lock().unwrap();
+  state.requests.push(request);
+
+  let authorized: bool = headers
+    .get(header::AUTHORIZATION)
+    .is_some_and(|value| value.as_bytes() == format!("Bearer {INTEGRATION_SECRET}").as_bytes());
+  if !authorized || !headers.contains_key("Notion-Version") {
+    return Some(
+      (
+        StatusCode::UNAUTHORIZED,
+        Json(json!({"object": "error", "code": "unauthorized"}))
+      ).into_response()
+    );
+  }
+
+  if state.rate_limited > 0 {
+    state.rate_limited -= 1;
+    return Some(
+      (
+        StatusCode::TOO_MANY_REQUESTS,
+        [(header::RETRY_AFTER, "0")],
+        Json(json!({"object": "error", "code": "rate_limited"}))
+      ).into_response()
+    );
+  }
+
+  None
+}
+
+/// One page of `results`, using the offset of the next result as cursor.
+fn paginate(
+  results: &[Value],
+  page_size: usize,
+  start_cursor: Option<&str>
+) -> Value {
+  let start: usize = start_cursor
+    .and_then(|cursor| cursor.parse().ok())
+    .unwrap_or(0)
+    .min(results.len());
+  let end: usize = (start + page_size.max(1)).min(results.len());
+  let has_more: bool = end < results.len();
+
+  json!(
+    {
+      "object": "list",
+      "results": &results[start..end],
+      "has_more": has_more,
+      "next_cursor": has_more.then(|| end.to_string())
+    }
+  )
+}
+
+async fn query_database(
+  State(state): State<SharedState>,
+  Path(id): Path<String>,
+  RawQuery(query): RawQuery,
+  headers: HeaderMap,
+  Json(body): Json<Value>
+) -> Response {
+  let since: Option<&str> = body["filter"]["last_edited_time"]["on_or_after"].as_str();
+  let ids_only: bool = query.is_some_and(|query| query == "filter_properties=title");
+
+  let request: String = format!(
+    "POST /v1/databases/{id}/query{}{}",
+    if ids_only { "?filter_properties=title" } else { "" },
+    since.map(|since| format!(" since {since}")).unwrap_or_default()
+  );
+  if let Some(response) = intercept(&state, &headers, request) {
+    return response;
+  }
+
+  if state.lock().unwrap().stalled.contains(&id) {
+    tokio::time::sleep(Duration::from_secs(3600)).await;
+  }
+
+  let state = state.lock().unwrap();
+
+  if state.failing.contains(&id) {
+    return (
+      StatusCode::INTERNAL_SERVER_ERROR,
+      Json(json!({"object": "error", "code": "internal_server_error"}))
+    ).into_response();
+  }
+
+  let Some(pages) = state.databases.get(&id) else {
+    return (
+      StatusCode::NOT_FOUND,
+      Json(json!({"object": "error", "code": "object_not_found"}))
+    ).into_response();
+  };
+
+  let pages: Vec<Value> = pages
+    .iter()
+    .filter(
+      |page| since.is_none_or(|since| page["last_edited_time"].as_str() >= Some(since))
+        && page["archived"] != true
+    )
+    .map(
+      |page| {
+        let mut page: Value = page.clone();
+        if ids_only {
+          page["properties"] = json!({});
+        }
+        page
+      }
+    )
+    .collect();
+
+  Json(
+    paginate(
+      &pages,
+      body["page_size"].as_u64().unwrap_or(100) as usize,
+      body["start_cursor"].as_str()
+    )
+  ).into_response()
+}
+
+/// A database with the properties of its first page.
+async fn retrieve_database(
+  State(state): State<SharedState>,
+  Path(id): Path<String>,
+  headers: HeaderMap
+) -> Response {
+  if let Some(response) = intercept(&state, &headers, format!("GET /v1/databases/{id}")) {
+    return response;
+  }
+
+  let state = state.lock().unwrap();
+
+  let Some(pages) = state.databases.get(&id) else {
+    return (
+      StatusCode::NOT_FOUND,
+      Json(json!({"object": "error", "code": "object_not_found"}))
+    ).into_response();
+  };
+
+  let properties: Map<String, Value> = pages
+    .first()
+    .and_then(|page| page["properties"].as_object())
+    .into_iter()
+    .flatten()
+    .map(|(name, property)| (name.clone(), json!({"name": name, "type": property["type"]})))
+    .collect();
+
+  Json(json!({"object": "database", "id": id, "properties": properties})).into_response()
+}
+
+async fn block_children(
+  State(state): State<SharedState>,
+  Path(id): Path<String>,
+  Query(query): Query<ChildrenQuery>,
+  headers: HeaderMap
+) -> Response {
+  if let Some(response) = intercept(&state, &headers, format!("GET /v1/blocks/{id}/children")) {
+    return response;
+  }
+
+  let state = state.lock().unwrap();
+
+  if state.failing.contains(&id) {
+    return (
+      StatusCode::INTERNAL_SERVER_ERROR,
+      Json(json!({"object": "error", "code": "internal_server_error"}))
+    ).into_response();
+  }
+
+  Json(
+    paginate(
+      state.blocks.get(&id).map(Vec::as_slice).unwrap_or_default(),
+      query.page_size.unwrap_or(100),
+      query.start_cursor.as_deref()
+    )
+  ).into_response()
+}
+
+async fn retrieve_page(
+  State(state): State<SharedState>,
+  Path(id): Path<String>,
+  headers: HeaderMap
+) -> Response {
+  if let Some(response) = intercept(&state, &headers, format!("GET /v1/pages/{id}")) {
+    return response;
+  }
+
+  let state = state.lock().unwrap();
+
+  let found: Option<(&String, &Value)> = state.databases
+    .iter()
+    .find_map(
+      |(database_id, pages)| pages
+        .iter()
+        .find(|page| page["id"] == id.as_str())
+        .map(|page| (database_id, page))
+    );
+
+  match found {
+    Some((database_id, page)) => {
+      let mut page: Value = page.clone();
+      page["parent"] = json!({"type": "database_id", "database_id": database_id});
+      page["archived"] = (page["archived"] == true).into();
+      Json(page).into_response()
+    },
+    None => (
+      StatusCode::NOT_FOUND,
+      Json(json!({"object": "error", "code": "object_not_found"}))
+    ).into_response()
+  }
+}
+
+async fn file(
+  State(state): State<SharedState>,
+  Path(name): Path<String>,
+  RawQuery(query): RawQuery
+) -> Response {
+  let name: String = match query {
+    Some(query) => format!("{name}?{query}"),
+    None => name
+  };
+
+  let mut state = state.lock().unwrap();
+  state.requests.push(format!("GET /files/{name}"));
+
+  match state.files.get(&name) {
+    Some((content_type, bytes)) => (
+      [(header::CONTENT_TYPE, content_type.clone())],
+      bytes.clone()
+    ).into_response(),
+    None => StatusCode::NOT_FOUND.into_response()
+  }
+}
@@ -0,0 +1,162 @@
+//! End-to-end tests against a mock Notion server.
+//!
+//! The client, cache and media store are process-wide, so every test runs on
+//! one shared runtime against one mock server, one test at a time.
+
+use std::{
+  env,
+  future::Future,
+  path::PathBuf,
+  sync::{Mutex, MutexGuard, OnceLock}
+};
+
+use axum::{
+  body::Body,
+  http::{Request, StatusCode, HeaderMap}
+};
+use hyper::body::{self, Bytes};
+use serde_json::Value;
+use tokio::runtime::Runtime;
+use tower::ServiceExt;
+
+use mock_notion::MockNotion;
+
+
+mod mock_notion;
+mod pages;
+mod sync;
+mod webhook;
+mod admin;
+mod conditional;
+mod listing;
+mod fixture_source;
+mod scheduler;
+mod schema;
+mod persist;
+
+
+static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+static MOCK_NOTION: OnceLock<MockNotion> = OnceLock::new();
+static TEST_LOCK: Mutex<()> = Mutex::new(());
+
+pub static INTEGRATION_SECRET: &str = "test-secret";
+pub static WEBHOOK_SECRET: &str = "webhook-secret";
+pub static ADMIN_TOKEN: &str = "admin-token";
+
+
+fn get_runtime() -> &'static Runtime {
+  RUNTIME.get_or_init(
+    || {
+      tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .expect("Build test runtime failed.")
+    }
+  )
+}
+
+/// Start the mock server and point every setting at it and at a scratch
+/// directory, before anything reads them.
+fn get_mock_notion() -> &'static MockNotion {
+  MOCK_NOTION.get_or_init(
+    || {
+      let mock_notion: MockNotion = get_runtime().block_on(MockNotion::start());
+
+      let directory: PathBuf = env::temp_dir().join(
+        format!("scaict-website-api-test-{}", std::process::id())
+      );
+      let _ = std::fs::remove_dir_all(&directory);
+
+      env::set_var("NOTION_API_BASE_URL", mock_notion.url());
+      env::set_var("INTEGRATION_SECRET", INTEGRATION_SECRET);
+      env::set_var("NOTION_PAGE_SIZE", "2");
+      env::set_var("NOTION_RETRY_MAX_ATTEMPTS", "3");
+      env::set_var("NOTION_RETRY_BASE_DELAY_MS", "1");
+      env::set_var("NOTION_RETRY_MAX_DELAY_MS", "10");
+      env::set_var("NOTION_REQUEST_TIMEOUT_MS", "500");
+      env::set_var("MEDIA_DIR", directory.join("media"));
+      env::set_var("SNAPSHOT_PATH", directory.join("snapshot.json"));
+      env::set_var("NOTION_WEBHOOK_SECRET", WEBHOOK_SECRET);
+      env::set_var("WEBHOOK_DEBOUNCE_MS", "50");
+      env::set_var("ADMIN_TOKENS", format!("old-token, {ADMIN_TOKEN}"));
+      env::remove_var("NOTION_FIXTURE_DIR");
+      env::remove_var("NOTION_SCHEMA_PATH");
+      env::remove_var("MEDIA_BASE_URL");
+
+      for data_type in crate::notion::types::NotionDataType::iterator() {
+        env::set_var(
+          format!("{}_DATABASE_ID", data_type.name().to_uppercase()),
+          pages::database_id(&data_type)
+        );
+      }
+
+      mock_notion
+    }
+  )
+}
+
+/// Run `test` on the shared runtime, holding the test lock.
+pub fn run<F: Future>(test: impl FnOnce(&'static MockNotion) -> F) -> F::Output {
+  let _guard: MutexGuard<()> = TEST_LOCK
+    .lock()
+    .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+  let mock_notion: &'static MockNotion = get_mock_notion();
+
+  get_runtime().block_on(test(mock_notion))
+}
+
+/// Send a request to the API.
+pub async fn send(request: Request<Body>) -> (StatusCode, HeaderMap, Bytes) {
+  let response = crate::app().oneshot(request).await.unwrap();
+
+  let status: StatusCode = response.status();
+  let headers: HeaderMap = response.headers().clone();
+
+  (status, headers, body::to_bytes(response.into_body()).await.unwrap())
+}
+
+pub async fn get(path: &str) -> (StatusCode, HeaderMap, Bytes) {
+  send(Request::get(path).body(Body::empty()).unwrap()).await
+}
+
+pub async fn get_json(path: &str) -> Value {
+  let (status, _, bytes) = get(path).await;
+
+  assert_eq!(status, StatusCode::OK, "GET {path}");
+
+  serde_json::from_slice(&bytes).unwrap()
+}
+
+/// Send a request to an admin route with the admin token.
+pub async fn send_admin(method: &str, path: &str) -> (StatusCode, HeaderMap, Bytes) {
+  send(
+    Request::builder()
+      .method(method)
+      .uri(path)
+      .header("Authorization", format!("Bearer {ADMIN_TOKEN}"))
+      .body(Body::empty())
+      .unwrap()
+  ).await
+}
+
+pub async fn get_admin_json(path: &str) -> Value {
+  let (status, _, bytes) = send_admin("GET", path).await;
+
+  assert_eq!(status, StatusCode::OK, "GET {path}");
+
+  serde_json::from_slice(&bytes).unwrap()
+}
+
+/// Sorted ids of an array of records.
+pub fn ids(records: &Value) -> Vec<&str> {
+  let mut ids: Vec<&str> = records
+    .as_array()
+    .unwrap()
+    .iter()
+    .map(|record| record["id"].as_str().unwrap())
+    .collect();
+
+  ids.sort();
+  ids
+}
@@ -0,0 +1,197 @@
+//! Notion pages and blocks shaped like API responses, using the property
+//! names of the bundled `schema.toml`.
+
+use serde_json::{Value, json};
+
+use crate::notion::types::NotionDataType;
+
+
+pub fn database_id(data_type: &NotionDataType) -> String {
+  format!("{}-database", data_type.name())
+}
+
+fn text(content: &str) -> Value {
+  json!(
+    [{
+      "type": "text",
+      "text": {"content": content, "link": null},
+      "plain_text": content,
+      "href": null,
+      "annotations": {
+        "bold": false, "italic": false, "strikethrough": false,
+        "underline": false, "code": false, "color": "default"
+      }
+    }]
+  )
+}
+
+fn title(content: &str) -> Value {
+  json!({"type": "title", "title": text(content)})
+}
+
+fn rich_text(content: &str) -> Value {
+  json!({"type": "rich_text", "rich_text": text(content)})
+}
+
+fn relation(ids: &[&str]) -> Value {
+  json!(
+    {
+      "type": "relation",
+      "relation": ids.iter().map(|id| json!({"id": id})).collect::<Vec<Value>>(),
+      "has_more": false
+    }
+  )
+}
+
+fn files(url: &str) -> Value {
+  json!(
+    {
+      "type": "files",
+      "files": [{"name": "file", "type": "external", "external": {"url": url}}]
+    }
+  )
+}
+
+fn page(id: &str, properties: Value) -> Value {
+  json!(
+    {
+      "object": "page",
+      "id": id,
+      "url": format!("https://www.notion.so/{}", id.replace('-', "")),
+      "created_time": "2023-08-01T00:00:00.000Z",
+      "last_edited_time": "2023-08-02T00:00:00.000Z",
+      "cover": null,
+      "icon": null,
+      "properties": properties
+    }
+  )
+}
+
+pub fn member(
+  id: &str,
+  name: &str,
+  groups: &[&str],
+  club: Option<&str>,
+  avatar_url: &str
+) -> Value {
+  page(
+    id,
+    json!(
+      {
+        "avatar": files(avatar_url),
+        "name": title(name),
+        "nickname": rich_text(&name.to_lowercase()),
+        "groups": relation(groups),
+        "description": rich_text(&format!("About {name}")),
+        "club": relation(club.as_slice()),
+        "club_positions": {
+          "type": "multi_select",
+          "multi_select": [{"name": "Member"}]
+        }
+      }
+    )
+  )
+}
+
+pub fn group(id: &str, name: &str, members: &[&str]) -> Value {
+  page(
+    id,
+    json!(
+      {
+        "name": title(name),
+        "description": rich_text(&format!("About {name}")),
+        "members": relation(members)
+      }
+    )
+  )
+}
+
+pub fn club(id: &str, name: &str, icon_url: &str) -> Value {
+  page(
+    id,
+    json!(
+      {
+        "name": title(name),
+        "description": rich_text(&format!("About {name}")),
+        "school": {"type": "select", "select": {"name": "SCAICT High"}},
+        "instagram_id": rich_text("scaict"),
+        "icon": files(icon_url)
+      }
+    )
+  )
+}
+
+pub fn event(
+  id: &str,
+  name: &str,
+  principal: &[&str],
+  thumbnail_url: &str
+) -> Value {
+  page(
+    id,
+    json!(
+      {
+        "date": {
+          "type": "date",
+          "date": {"start": "2023-08-10", "end": "2023-08-12", "time_zone": null}
+        },
+        "name": title(name),
+        "description": rich_text(&format!("About {name}")),
+        "thumbnail": files(thumbnail_url),
+        "principal": relation(principal)
+      }
+    )
+  )
+}
+
+pub fn article(id: &str, title_text: &str) -> Value {
+  page(
+    id,
+    json!(
+      {
+        "title": title(title_text),
+        "description": rich_text(&format!("About {title_text}")),
+        "tags": {"type": "multi_select", "multi_select": [{"name": "news"}]},
+        "created_at": {"type": "created_time", "created_time": "2023-08-01T00:00:00.000Z"},
+        "updated_at": {"type": "last_edited_time", "last_edited_time": "2023-08-02T00:00:00.000Z"}
+      }
+    )
+  )
+}
+
+pub fn sponsor(id: &str, name: &str, icon_url: &str) -> Value {
+  page(
+    id,
+    json!(
+      {
+        "name": title(name),
+        "description": rich_text(&format!("About {name}")),
+        "url": {"type": "url", "url": "https://example.com"},
+        "icon": files(icon_url)
+      }
+    )
+  )
+}
+
+/// Mark a page as edited at `time`, an RFC 3339 timestamp.
+pub fn edit(page: &mut Value, time: &str) {
+  page["last_edited_time"] = time.into();
+}
+
+/// Move a page to the trash. It is left out of queries but can still be
+/// retrieved.
+pub fn archive(page: &mut Value) {
+  page["archived"] = true.into();
+}
+
+pub fn block(id: &str, block_type: &str, content: &str, has_children: bool) -> Value {
+  json!(
+    {
+      "object": "block",
+      "id": id,
+      "type": block_type,
+      "has_children": has_children,
+      block_type: {"rich_text": text(content), "color": "default"}
+    }
+  )
+}
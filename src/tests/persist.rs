@@ -0,0 +1,74 @@
+use std::env;
+
+use axum::http::{HeaderMap, StatusCode, header};
+use serde_json::Value;
+
+use crate::notion::{
+  cache::{CacheStorage, Watermarks},
+  client::SyncMode,
+  persist,
+  relations::Dataset
+};
+
+use super::{
+  run,
+  get,
+  get_json,
+  ids,
+  sync::{update_all, workspace}
+};
+
+
+#[test]
+fn restores_the_saved_snapshot_with_its_relations() {
+  run(
+    |mock_notion| async move {
+      mock_notion.reset(workspace(mock_notion));
+      update_all(SyncMode::Full).await;
+
+      let member: Value = get_json("/members/member-1").await;
+      let headers: HeaderMap = get("/members/member-1").await.1;
+      let generation: u64 = CacheStorage::get().snapshot().generation;
+      persist::save(&CacheStorage::get().snapshot(), &CacheStorage::get().synced_at()).await.unwrap();
+
+      CacheStorage::get().publish(Dataset::new(), Watermarks::new());
+      assert_eq!(get("/members/member-1").await.0, StatusCode::NOT_FOUND);
+
+      let (saved_generation, dataset, watermarks, synced_at, changed_at) = persist::load().await.unwrap().unwrap();
+      assert_eq!(saved_generation, generation);
+      CacheStorage::get().restore(saved_generation, dataset, watermarks, synced_at, changed_at);
+
+      // Relation ids are not stored; they are recovered from the embedded
+      // records, which the indexes and the next resolution rely on.
+      assert_eq!(get_json("/members/member-1").await, member);
+      assert_eq!(member["club"]["id"], "club-1");
+      assert_eq!(ids(&member["groups"]), ["group-1"]);
+      let restored: HeaderMap = get("/members/member-1").await.1;
+      assert_eq!(restored[header::ETAG], headers[header::ETAG]);
+      assert_eq!(restored[header::LAST_MODIFIED], headers[header::LAST_MODIFIED]);
+      assert_eq!(ids(&get_json("/members?club=club-1").await), ["member-1", "member-3"]);
+      assert_eq!(ids(&get_json("/members?group=group-1").await), ["member-1", "member-2"]);
+    }
+  );
+}
+
+#[test]
+fn refuses_snapshots_of_other_versions() {
+  run(
+    |mock_notion| async move {
+      mock_notion.reset(workspace(mock_notion));
+      update_all(SyncMode::Full).await;
+
+      let path: String = env::var("SNAPSHOT_PATH").unwrap();
+      let mut file: Value = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+      file["version"] = 1.into();
+      std::fs::write(&path, serde_json::to_vec(&file).unwrap()).unwrap();
+
+      let error: String = persist::load().await.unwrap_err().to_string();
+      assert_eq!(error, "Snapshot version 1 is not supported (expected 2).");
+
+      std::fs::remove_file(&path).unwrap();
+      assert!(persist::load().await.unwrap().is_none());
+    }
+  );
+}
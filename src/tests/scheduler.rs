@@ -0,0 +1,104 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::{
+  notion::{client::SyncMode, types::NotionDataType},
+  scheduler::{take_due_at, TypeSchedule, JITTER_RATIO, SCAN_INTERVAL}
+};
+
+
+/// A type synced every minute, next run at `next_run`, with its next scan and
+/// full sync `scan` and `full_sync` after `now`.
+fn schedule(
+  now: DateTime<Utc>,
+  next_run: Duration,
+  scan: Duration,
+  full_sync: Duration
+) -> TypeSchedule {
+  TypeSchedule {
+    interval: 60,
+    next_run: now + next_run,
+    next_scan: now + scan,
+    next_full_sync: now + full_sync,
+    last_run: None,
+    running: false
+  }
+}
+
+fn sorted(mut due: Vec<(NotionDataType, SyncMode)>) -> Vec<(NotionDataType, SyncMode)> {
+  due.sort_by_key(|(data_type, _)| data_type.name());
+  due
+}
+
+
+#[test]
+fn due_types_are_synced_in_the_most_thorough_mode_due() {
+  let now: DateTime<Utc> = Utc::now();
+  let hour: Duration = Duration::hours(1);
+  let mut schedules: HashMap<NotionDataType, TypeSchedule> = HashMap::from(
+    [
+      (NotionDataType::Article, schedule(now, Duration::zero(), hour, hour)),
+      (NotionDataType::Club, schedule(now, -hour, Duration::zero(), hour)),
+      (NotionDataType::Event, schedule(now, Duration::zero(), -hour, -hour)),
+      (NotionDataType::Sponsor, schedule(now, Duration::seconds(1), -hour, -hour))
+    ]
+  );
+
+  assert_eq!(
+    sorted(take_due_at(&mut schedules, now)),
+    [
+      (NotionDataType::Article, SyncMode::Incremental),
+      (NotionDataType::Club, SyncMode::IncrementalWithScan),
+      (NotionDataType::Event, SyncMode::Full)
+    ]
+  );
+
+  let scan: DateTime<Utc> = now + Duration::from_std(SCAN_INTERVAL).unwrap();
+  assert_eq!(schedules[&NotionDataType::Article].next_scan, now + hour);
+  assert_eq!(schedules[&NotionDataType::Club].next_scan, scan);
+  assert_eq!(schedules[&NotionDataType::Event].next_scan, scan);
+  assert!(schedules[&NotionDataType::Event].running);
+  assert_eq!(schedules[&NotionDataType::Event].last_run, Some(now));
+
+  let sponsor: &TypeSchedule = &schedules[&NotionDataType::Sponsor];
+  assert!(!sponsor.running);
+  assert_eq!(sponsor.next_run, now + Duration::seconds(1));
+  assert_eq!(sponsor.last_run, None);
+}
+
+#[test]
+fn running_types_are_skipped_until_their_next_turn() {
+  let now: DateTime<Utc> = Utc::now();
+  let mut running: TypeSchedule = schedule(now, Duration::zero(), -Duration::hours(1), -Duration::hours(1));
+  running.running = true;
+  let mut schedules: HashMap<NotionDataType, TypeSchedule> = HashMap::from(
+    [(NotionDataType::Member, running)]
+  );
+
+  assert_eq!(take_due_at(&mut schedules, now), []);
+
+  let member: &TypeSchedule = &schedules[&NotionDataType::Member];
+  assert!(member.next_run > now, "a skipped run was not moved forward");
+  assert_eq!(member.next_scan, now - Duration::hours(1), "a skipped run consumed the scan");
+  assert_eq!(member.last_run, None);
+
+  assert_eq!(take_due_at(&mut schedules, now), [], "a run was due twice at once");
+}
+
+#[test]
+fn next_runs_are_jittered_within_bounds() {
+  let now: DateTime<Utc> = Utc::now();
+  let interval: Duration = Duration::seconds(60);
+  let latest: Duration = Duration::milliseconds((60_000.0 * (1.0 + JITTER_RATIO)) as i64);
+
+  for _ in 0..200 {
+    let mut schedules: HashMap<NotionDataType, TypeSchedule> = HashMap::from(
+      [(NotionDataType::Group, schedule(now, Duration::zero(), Duration::hours(1), Duration::hours(1)))]
+    );
+    take_due_at(&mut schedules, now);
+
+    let delay: Duration = schedules[&NotionDataType::Group].next_run - now;
+    assert!(delay >= interval && delay <= latest, "next run after {delay}");
+  }
+}
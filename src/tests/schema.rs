@@ -0,0 +1,193 @@
+use std::{env, path::PathBuf, sync::Arc, time::Duration};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde_json::{Map, Value, json};
+
+use crate::{
+  notion::{
+    cache::{CacheStorage, Snapshot},
+    client::{validate_schema, SyncMode},
+    schema::Schema,
+    types::NotionDataType
+  },
+  scheduler::{full_sync_delay, EXPIRY_MARGIN, MAX_CACHE_AGE}
+};
+
+use super::{
+  run,
+  get_json,
+  mock_notion::MockState,
+  pages,
+  sync::{update_all, workspace}
+};
+
+
+static BUNDLED_SCHEMA: &str = include_str!("../../schema.toml");
+
+
+/// Parses pages with the mapping at `NOTION_SCHEMA_PATH` until dropped.
+struct Mapping;
+
+impl Mapping {
+  fn install(source: &str) -> Mapping {
+    let path: PathBuf = env::temp_dir().join(
+      format!("scaict-website-api-schema-{}.toml", std::process::id())
+    );
+    std::fs::write(&path, source).unwrap();
+    env::set_var("NOTION_SCHEMA_PATH", &path);
+    Schema::reload().unwrap();
+
+    Mapping
+  }
+}
+
+impl Drop for Mapping {
+  fn drop(self: &mut Self) {
+    env::remove_var("NOTION_SCHEMA_PATH");
+    Schema::reload().unwrap();
+  }
+}
+
+/// Sponsor pages of `state`.
+fn sponsors(state: &mut MockState) -> &mut Vec<Value> {
+  state.databases
+    .get_mut(&pages::database_id(&NotionDataType::Sponsor))
+    .unwrap()
+}
+
+
+#[test]
+fn bundled_schema_maps_every_field() {
+  assert!(Schema::parse(BUNDLED_SCHEMA).is_ok());
+
+  let error: String = Schema::parse(
+    &BUNDLED_SCHEMA
+      .replace("url = { property = \"url\", type = \"url\" }", "url = { property = \"url\", type = \"relation\" }")
+      .replace("school = { property = \"school\", type = \"select\" }\n", "")
+  ).unwrap_err().to_string();
+  assert!(error.contains("Missing field `Club.school`."), "{error}");
+  assert!(error.contains("Field `Sponsor.url` cannot be read from a `relation` property."), "{error}");
+}
+
+#[test]
+fn properties_are_read_through_the_mapping() {
+  run(
+    |mock_notion| async move {
+      let _mapping: Mapping = Mapping::install(
+        &BUNDLED_SCHEMA.replace(
+          "[sponsor]\nname = { property = \"name\", type = \"title\" }",
+          "[sponsor]\nname = { property = \"Company\", type = \"title\" }"
+        )
+      );
+
+      let mut state: MockState = workspace(mock_notion);
+      for sponsor in sponsors(&mut state) {
+        let properties: &mut Map<String, Value> = sponsor["properties"].as_object_mut().unwrap();
+        let name: Value = properties.remove("name").unwrap();
+        properties.insert("Company".into(), name);
+      }
+      mock_notion.reset(state);
+
+      update_all(SyncMode::Full).await;
+
+      assert_eq!(get_json("/sponsors/sponsor-1").await["name"], "Acme");
+      assert_eq!(validate_schema().await.unwrap(), Vec::<String>::new());
+
+      let mut state: MockState = workspace(mock_notion);
+      let properties: &mut Map<String, Value> = sponsors(&mut state)[0]["properties"]
+        .as_object_mut()
+        .unwrap();
+      properties.remove("description");
+      properties.insert("url".into(), json!({"type": "rich_text", "rich_text": []}));
+      mock_notion.reset(state);
+
+      assert_eq!(
+        validate_schema().await.unwrap(),
+        [
+          "`Sponsor.description`: property `description` does not exist.",
+          "`Sponsor.name`: property `Company` does not exist.",
+          "`Sponsor.url`: property `url` is `rich_text`, expected `url`."
+        ]
+      );
+    }
+  );
+}
+
+#[test]
+fn page_covers_icons_and_hosted_files_are_parsed() {
+  run(
+    |mock_notion| async move {
+      let _mapping: Mapping = Mapping::install(
+        &BUNDLED_SCHEMA
+          .replace(
+            "instagram_id = { property = \"instagram_id\", type = \"rich_text\" }\nicon = { property = \"icon\", type = \"files\" }",
+            "instagram_id = { property = \"instagram_id\", type = \"rich_text\" }\nicon = { type = \"cover\" }"
+          )
+          .replace(
+            "url = { property = \"url\", type = \"url\" }\nicon = { property = \"icon\", type = \"files\" }",
+            "url = { property = \"url\", type = \"url\" }\nicon = { type = \"icon\" }"
+          )
+      );
+
+      // Hosted files that cannot be mirrored keep their signed url, which
+      // stops working at its expiry time.
+      let expiry = |hours: i64| -> String {
+        (Utc::now() + chrono::Duration::hours(hours)).to_rfc3339_opts(SecondsFormat::Millis, true)
+      };
+      let hosted = |name: &str, expiry_time: &str| -> Value {
+        json!(
+          {
+            "type": "file",
+            "file": {
+              "url": format!("{}?X-Amz-Signature={name}", mock_notion.file_url(name)),
+              "expiry_time": expiry_time
+            }
+          }
+        )
+      };
+      let (sponsor_expiry, event_expiry): (String, String) = (expiry(2), expiry(30));
+
+      let mut state: MockState = workspace(mock_notion);
+      let cover: String = mock_notion.file_url("image.png");
+      state.databases
+        .get_mut(&pages::database_id(&NotionDataType::Club))
+        .unwrap()[0]["cover"] = json!({"type": "external", "external": {"url": cover}});
+      sponsors(&mut state)[0]["icon"] = hosted("icon.png", &sponsor_expiry);
+      let event: &mut Value = &mut state.databases
+        .get_mut(&pages::database_id(&NotionDataType::Event))
+        .unwrap()[0];
+      let mut thumbnail: Value = hosted("thumbnail.png", &event_expiry);
+      thumbnail["name"] = "thumbnail.png".into();
+      event["properties"]["thumbnail"]["files"] = json!([thumbnail]);
+      mock_notion.reset(state);
+
+      update_all(SyncMode::Full).await;
+
+      let icon: Value = get_json("/clubs/club-1").await["icon"].clone();
+      assert!(icon.as_str().unwrap().starts_with("/media/"), "cover {icon} is not mirrored");
+      assert_eq!(
+        get_json("/sponsors/sponsor-1").await["icon"],
+        format!("{}?X-Amz-Signature=icon.png", mock_notion.file_url("icon.png"))
+      );
+      assert_eq!(
+        get_json("/events/event-1").await["thumbnail"],
+        format!("{}?X-Amz-Signature=thumbnail.png", mock_notion.file_url("thumbnail.png"))
+      );
+
+      let parsed = |time: &str| -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(time).unwrap().with_timezone(&Utc)
+      };
+      let snapshot: Arc<Snapshot> = CacheStorage::get().snapshot();
+      assert_eq!(snapshot.expiry_time(&NotionDataType::Sponsor), Some(parsed(&sponsor_expiry)));
+      assert_eq!(snapshot.expiry_time(&NotionDataType::Event), Some(parsed(&event_expiry)));
+      assert_eq!(snapshot.expiry_time(&NotionDataType::Club), None);
+
+      // The next full sync fetches new urls shortly before they expire.
+      let delay: Duration = full_sync_delay(&NotionDataType::Sponsor);
+      let expected: Duration = Duration::from_secs(2 * 3600) - EXPIRY_MARGIN;
+      assert!(delay <= expected && delay > expected - Duration::from_secs(60), "{delay:?}");
+      assert_eq!(full_sync_delay(&NotionDataType::Event), MAX_CACHE_AGE);
+      assert_eq!(full_sync_delay(&NotionDataType::Club), MAX_CACHE_AGE);
+    }
+  );
+}
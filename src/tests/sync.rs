@@ -0,0 +1,599 @@
+use std::{env, io::Cursor, path::PathBuf};
+
+use axum::http::{StatusCode, header};
+use chrono::DateTime;
+use image::{DynamicImage, ImageOutputFormat, RgbImage};
+use serde_json::{Value, json};
+
+use crate::notion::{
+  cache::CacheStorage,
+  client::{update_types, SyncMode},
+  types::NotionDataType
+};
+
+use super::{
+  run,
+  get,
+  get_json,
+  get_admin_json,
+  ids,
+  mock_notion::{MockNotion, MockState},
+  pages
+};
+
+
+/// Sync every type. Failures are asserted on through the served data.
+pub async fn update_all(mode: SyncMode) {
+  let _ = update_types(
+    &NotionDataType::iterator()
+      .map(|data_type| (data_type, mode))
+      .collect::<Vec<_>>()
+  ).await;
+}
+
+pub fn png() -> Vec<u8> {
+  let mut bytes: Vec<u8> = Vec::new();
+
+  DynamicImage::ImageRgb8(RgbImage::new(8, 8))
+    .write_to(&mut Cursor::new(&mut bytes), ImageOutputFormat::Png)
+    .unwrap();
+
+  bytes
+}
+
+/// Three members, so the member query spans two pages of two, and an
+/// article whose blocks span two pages and nest.
+pub fn workspace(mock_notion: &MockNotion) -> MockState {
+  let mut state: MockState = MockState::default();
+
+  state.files.insert("image.png".into(), ("image/png".into(), png()));
+  let image_url: String = mock_notion.file_url("image.png");
+
+  state.databases.insert(
+    pages::database_id(&NotionDataType::Club),
+    vec![pages::club("club-1", "SCAICT", &image_url)]
+  );
+  state.databases.insert(
+    pages::database_id(&NotionDataType::Group),
+    vec![
+      pages::group("group-1", "Core", &["member-1", "member-2"]),
+      pages::group("group-2", "Design", &["member-3"])
+    ]
+  );
+  state.databases.insert(
+    pages::database_id(&NotionDataType::Member),
+    vec![
+      pages::member("member-1", "Alice", &["group-1"], Some("club-1"), &image_url),
+      pages::member("member-2", "Bob", &["group-1"], None, &image_url),
+      pages::member("member-3", "Carol", &["group-2"], Some("club-1"), &image_url)
+    ]
+  );
+  state.databases.insert(
+    pages::database_id(&NotionDataType::Event),
+    vec![pages::event("event-1", "Camp", &["member-1", "member-unknown"], &image_url)]
+  );
+  state.databases.insert(
+    pages::database_id(&NotionDataType::Article),
+    vec![pages::article("article-1", "Hello")]
+  );
+  state.databases.insert(
+    pages::database_id(&NotionDataType::Sponsor),
+    vec![pages::sponsor("sponsor-1", "Acme", &image_url)]
+  );
+
+  state.blocks.insert(
+    "article-1".into(),
+    vec![
+      pages::block("block-1", "heading_2", "Welcome", false),
+      pages::block("block-2", "paragraph", "First paragraph", false),
+      pages::block("block-3", "bulleted_list_item", "Outer", true)
+    ]
+  );
+  state.blocks.insert(
+    "block-3".into(),
+    vec![pages::block("block-4", "bulleted_list_item", "Inner", false)]
+  );
+
+  state
+}
+
+#[test]
+fn serves_every_route_after_sync() {
+  run(
+    |mock_notion| async move {
+      mock_notion.reset(workspace(mock_notion));
+
+      update_all(SyncMode::Full).await;
+
+      assert!(get_json("/version").await["version"].is_string());
+      assert_eq!(get("/robots.txt").await.0, StatusCode::OK);
+      assert_eq!(get("/repo").await.0, StatusCode::PERMANENT_REDIRECT);
+
+      for (path, expected) in [
+        ("/members", vec!["member-1", "member-2", "member-3"]),
+        ("/groups", vec!["group-1", "group-2"]),
+        ("/clubs", vec!["club-1"]),
+        ("/events", vec!["event-1"]),
+        ("/articles", vec!["article-1"]),
+        ("/sponsors", vec!["sponsor-1"])
+      ] {
+        assert_eq!(ids(&get_json(path).await), expected, "GET {path}");
+        assert_eq!(
+          get_json(&format!("{path}/{}", expected[0])).await["id"],
+          expected[0]
+        );
+        assert_eq!(get(&format!("{path}/missing")).await.0, StatusCode::NOT_FOUND);
+      }
+
+      let member: Value = get_json("/members/member-1").await;
+      assert_eq!(member["name"], "Alice");
+      assert_eq!(member["club"]["id"], "club-1");
+      assert_eq!(ids(&member["groups"]), ["group-1"]);
+      assert!(member["groups"][0]["members"].is_null());
+
+      let group: Value = get_json("/groups/group-1").await;
+      assert_eq!(ids(&group["members"]), ["member-1", "member-2"]);
+      assert!(group["members"][0]["groups"].is_null());
+
+      let event: Value = get_json("/events/event-1").await;
+      assert_eq!(ids(&event["principal"]), ["member-1"]);
+      assert_eq!(ids(&event["principal"][0]["groups"]), ["group-1"]);
+
+      let article: Value = get_json("/articles/article-1").await;
+      let content: &Vec<Value> = article["content"].as_array().unwrap();
+      assert_eq!(content.len(), 3);
+      assert_eq!(content[2]["children"][0]["id"], "block-4");
+      let html: Value = get_json("/articles/article-1?format=html").await;
+      assert!(html["content"].as_str().unwrap().contains("<h2>Welcome</h2>"));
+      let markdown: Value = get_json("/articles/article-1?format=markdown").await;
+      assert!(markdown["content"].as_str().unwrap().contains("  - Inner"));
+
+      let avatar: &str = member["avatar"].as_str().unwrap();
+      assert!(avatar.starts_with("/media/"), "avatar {avatar} is not mirrored");
+      let (status, headers, bytes) = get(avatar).await;
+      assert_eq!(status, StatusCode::OK);
+      assert_eq!(headers[header::CONTENT_TYPE], "image/png");
+      assert_eq!(bytes.to_vec(), png());
+      let (status, headers, _) = get(&format!("{avatar}?w=4&format=webp")).await;
+      assert_eq!(status, StatusCode::OK);
+      assert_eq!(headers[header::CONTENT_TYPE], "image/webp");
+      let variants: PathBuf = PathBuf::from(env::var("MEDIA_DIR").unwrap()).join("variants");
+      let rendered: usize = std::fs::read_dir(&variants).unwrap().count();
+      for w in [1, 20, 32] {
+        assert_eq!(get(&format!("{avatar}?w={w}&format=webp")).await.0, StatusCode::OK);
+      }
+      assert_eq!(std::fs::read_dir(&variants).unwrap().count(), rendered, "sizes were not rounded to one variant");
+      assert_eq!(get("/media/not-a-hash").await.0, StatusCode::NOT_FOUND);
+      assert_eq!(get(&format!("{avatar}?w=0")).await.0, StatusCode::BAD_REQUEST);
+
+      let report: Value = get_admin_json("/admin/sync-report").await;
+      assert_eq!(report["member"]["parsed"], 3);
+      assert_eq!(report["member"]["skipped"].as_array().unwrap().len(), 0);
+      // Reported in the same format as the freshness of each type.
+      let freshness: Value = get_admin_json("/admin/freshness").await;
+      for synced_at in [&report["member"]["synced_at"], &freshness["member"]["synced_at"]] {
+        assert!(DateTime::parse_from_rfc3339(synced_at.as_str().unwrap()).is_ok(), "{synced_at}");
+      }
+
+      let schedule: Value = get_admin_json("/admin/schedule").await;
+      assert_eq!(schedule.as_object().unwrap().len(), 6);
+      assert_eq!(schedule["article"]["interval"], 60);
+
+      let requests: Vec<String> = mock_notion.requests();
+      let member_queries: usize = requests
+        .iter()
+        .filter(|request| request.starts_with("POST /v1/databases/member-database/query"))
+        .count();
+      assert_eq!(member_queries, 2);
+      let article_children: usize = requests
+        .iter()
+        .filter(|request| *request == "GET /v1/blocks/article-1/children")
+        .count();
+      assert_eq!(article_children, 2);
+    }
+  );
+}
+
+#[test]
+fn retries_rate_limited_requests() {
+  run(
+    |mock_notion| async move {
+      let mut state: MockState = workspace(mock_notion);
+      state.rate_limited = 2;
+      mock_notion.reset(state);
+
+      update_all(SyncMode::Full).await;
+
+      assert_eq!(ids(&get_json("/clubs").await), ["club-1"]);
+      let club_queries: usize = mock_notion
+        .requests()
+        .iter()
+        .filter(|request| request.starts_with("POST /v1/databases/club-database/query"))
+        .count();
+      assert_eq!(club_queries, 3);
+    }
+  );
+}
+
+#[test]
+fn gives_up_on_stalled_requests() {
+  run(
+    |mock_notion| async move {
+      mock_notion.reset(workspace(mock_notion));
+      update_all(SyncMode::Full).await;
+
+      let mut state: MockState = workspace(mock_notion);
+      state.stalled.insert(pages::database_id(&NotionDataType::Sponsor));
+      mock_notion.reset(state);
+
+      update_all(SyncMode::Full).await;
+
+      let freshness: Value = get_admin_json("/admin/freshness").await;
+      assert_eq!(freshness["sponsor"]["stale"], true);
+      assert!(freshness["sponsor"]["error"].as_str().unwrap().contains("timed out"));
+      assert_eq!(freshness["club"]["stale"], false);
+      assert_eq!(ids(&get_json("/sponsors").await), ["sponsor-1"]);
+    }
+  );
+}
+
+#[test]
+fn mirrors_only_images_by_their_full_url() {
+  run(
+    |mock_notion| async move {
+      let mut state: MockState = workspace(mock_notion);
+      state.files.insert("uc?id=1".into(), ("image/png".into(), png()));
+      state.files.insert("uc?id=2".into(), ("image/gif".into(), b"GIF89a".to_vec()));
+      state.files.insert("page.html".into(), ("text/html".into(), b"<script></script>".to_vec()));
+      state.databases.insert(
+        pages::database_id(&NotionDataType::Sponsor),
+        vec![
+          pages::sponsor("sponsor-1", "Acme", &format!("{}?id=1", mock_notion.file_url("uc"))),
+          pages::sponsor("sponsor-2", "Globex", &format!("{}?id=2", mock_notion.file_url("uc")))
+        ]
+      );
+      state.databases.insert(
+        pages::database_id(&NotionDataType::Club),
+        vec![pages::club("club-1", "SCAICT", &mock_notion.file_url("page.html"))]
+      );
+      mock_notion.reset(state);
+
+      update_all(SyncMode::Full).await;
+
+      let first: Value = get_json("/sponsors/sponsor-1").await["icon"].clone();
+      let second: Value = get_json("/sponsors/sponsor-2").await["icon"].clone();
+      assert_ne!(first, second);
+
+      for (icon, content_type) in [(first, "image/png"), (second, "image/gif")] {
+        let (status, headers, _) = get(icon.as_str().unwrap()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(headers[header::CONTENT_TYPE], content_type);
+        assert_eq!(headers[header::X_CONTENT_TYPE_OPTIONS], "nosniff");
+      }
+
+      assert_eq!(get_json("/clubs/club-1").await["icon"], mock_notion.file_url("page.html"));
+    }
+  );
+}
+
+#[test]
+fn skips_malformed_rows() {
+  run(
+    |mock_notion| async move {
+      let mut state: MockState = workspace(mock_notion);
+      let members: &mut Vec<Value> = state.databases
+        .get_mut(&pages::database_id(&NotionDataType::Member))
+        .unwrap();
+      members[1]["properties"]
+        .as_object_mut()
+        .unwrap()
+        .remove("name");
+      members[2]["properties"]["avatar"]["files"] = json!([]);
+      mock_notion.reset(state);
+
+      update_all(SyncMode::Full).await;
+
+      assert_eq!(ids(&get_json("/members").await), ["member-1", "member-3"]);
+      assert_eq!(get_json("/members/member-3").await["avatar"], "");
+      assert_eq!(ids(&get_json("/groups/group-1").await["members"]), ["member-1"]);
+
+      let report: Value = get_admin_json("/admin/sync-report").await;
+      assert_eq!(report["member"]["parsed"], 2);
+      assert_eq!(report["member"]["skipped"][0]["page_id"], "member-2");
+      assert_eq!(report["member"]["skipped"][0]["field"], "properties.name");
+    }
+  );
+}
+
+#[test]
+fn joins_every_rich_text_span() {
+  run(
+    |mock_notion| async move {
+      let span = |content: &str, annotations: Value, href: Value| -> Value {
+        json!(
+          {
+            "type": "text",
+            "text": {"content": content, "link": null},
+            "plain_text": content,
+            "href": href,
+            "annotations": annotations
+          }
+        )
+      };
+      let plain: Value = json!(
+        {
+          "bold": false, "italic": false, "strikethrough": false,
+          "underline": false, "code": false, "color": "default"
+        }
+      );
+      let mut bold: Value = plain.clone();
+      bold["bold"] = true.into();
+      let mut link: Value = plain.clone();
+      link["italic"] = true.into();
+      link["color"] = "blue".into();
+
+      let mut state: MockState = workspace(mock_notion);
+      let members: &mut Vec<Value> = state.databases
+        .get_mut(&pages::database_id(&NotionDataType::Member))
+        .unwrap();
+      members[0]["properties"]["name"]["title"] = json!(
+        [span("Ada ", plain.clone(), Value::Null), span("Lovelace", bold.clone(), Value::Null)]
+      );
+      members[0]["properties"]["description"]["rich_text"] = json!(
+        [
+          span("Writes ", plain.clone(), Value::Null),
+          span("notes", link.clone(), "https://example.com/notes".into()),
+          span(".", plain.clone(), Value::Null)
+        ]
+      );
+      members[0]["properties"]["nickname"]["rich_text"] = json!([]);
+      mock_notion.reset(state);
+
+      update_all(SyncMode::Full).await;
+
+      let member: Value = get_json("/members/member-1").await;
+      assert_eq!(member["name"], "Ada Lovelace");
+      assert_eq!(member["nickname"], "");
+      assert_eq!(member["description"], "Writes notes.");
+
+      let spans: &Vec<Value> = member["description_rich_text"].as_array().unwrap();
+      assert_eq!(spans.len(), 3);
+      assert_eq!(spans[1]["plain_text"], "notes");
+      assert_eq!(spans[1]["href"], "https://example.com/notes");
+      assert_eq!(spans[1]["annotations"], link);
+      assert_eq!(spans[0]["annotations"], plain);
+    }
+  );
+}
+
+#[test]
+fn renders_only_safe_urls() {
+  run(
+    |mock_notion| async move {
+      let mut state: MockState = workspace(mock_notion);
+      let mut paragraph: Value = pages::block("block-1", "paragraph", "", false);
+      paragraph["paragraph"]["rich_text"] = json!(
+        [
+          {"type": "text", "plain_text": "bad", "href": "JavaScript:alert(1)"},
+          {"type": "text", "plain_text": "good", "href": "https://example.com/a (b)"}
+        ]
+      );
+      let image = |id: &str, url: &str| json!(
+        {
+          "object": "block",
+          "id": id,
+          "type": "image",
+          "has_children": false,
+          "image": {"type": "external", "external": {"url": url}, "caption": []}
+        }
+      );
+      state.blocks.insert(
+        "article-1".into(),
+        vec![
+          paragraph,
+          image("block-2", "javascript:alert(1)"),
+          image("block-3", &mock_notion.file_url("image.png"))
+        ]
+      );
+      mock_notion.reset(state);
+
+      update_all(SyncMode::Full).await;
+
+      let html: Value = get_json("/articles/article-1?format=html").await;
+      let html: &str = html["content"].as_str().unwrap();
+      assert!(!html.to_lowercase().contains("javascript"), "{html}");
+      assert!(html.contains(r#"<p>bad<a href="https://example.com/a (b)">good</a></p>"#), "{html}");
+      assert_eq!(html.matches("<img").count(), 1);
+      assert!(html.contains(r#"<img src="/media/"#), "{html}");
+
+      let markdown: Value = get_json("/articles/article-1?format=markdown").await;
+      let markdown: &str = markdown["content"].as_str().unwrap();
+      assert!(!markdown.to_lowercase().contains("javascript"), "{markdown}");
+      assert!(markdown.contains("bad[good](https://example.com/a%20%28b%29)"), "{markdown}");
+      assert_eq!(markdown.matches("![").count(), 1);
+    }
+  );
+}
+
+#[test]
+fn keeps_articles_whose_content_fails() {
+  run(
+    |mock_notion| async move {
+      mock_notion.reset(workspace(mock_notion));
+      update_all(SyncMode::Full).await;
+      let article: Value = get_json("/articles/article-1").await;
+
+      let mut state: MockState = workspace(mock_notion);
+      state.databases
+        .get_mut(&pages::database_id(&NotionDataType::Article))
+        .unwrap()
+        .push(pages::article("article-2", "Recap"));
+      state.failing.insert("article-1".into());
+      mock_notion.reset(state);
+
+      update_all(SyncMode::Full).await;
+
+      assert_eq!(ids(&get_json("/articles").await), ["article-1", "article-2"]);
+      assert_eq!(get_json("/articles/article-1").await, article);
+      let (_, headers, _) = get("/articles").await;
+      assert_eq!(headers["X-Data-Stale"], "true");
+
+      let report: Value = get_admin_json("/admin/sync-report").await;
+      assert_eq!(report["article"]["parsed"], 2);
+      assert_eq!(report["article"]["skipped"], json!([]));
+
+      let freshness: Value = get_admin_json("/admin/freshness").await;
+      assert_eq!(freshness["article"]["stale"], true);
+      let error: &str = freshness["article"]["error"].as_str().unwrap();
+      assert!(error.contains("page article-1: Fetch content failed"), "{error}");
+
+      update_all(SyncMode::Incremental).await;
+
+      assert_eq!(ids(&get_json("/articles").await), ["article-1", "article-2"]);
+      assert_eq!(get_admin_json("/admin/freshness").await["article"]["stale"], true);
+    }
+  );
+}
+
+#[test]
+fn keeps_cached_data_when_a_database_fails() {
+  run(
+    |mock_notion| async move {
+      mock_notion.reset(workspace(mock_notion));
+      update_all(SyncMode::Full).await;
+
+      let mut state: MockState = workspace(mock_notion);
+      state.databases.insert(
+        pages::database_id(&NotionDataType::Sponsor),
+        Vec::new()
+      );
+      state.failing.insert(pages::database_id(&NotionDataType::Club));
+      mock_notion.reset(state);
+
+      update_all(SyncMode::Full).await;
+
+      assert_eq!(ids(&get_json("/clubs").await), ["club-1"]);
+      assert_eq!(get_json("/members/member-1").await["club"]["id"], "club-1");
+      assert_eq!(ids(&get_json("/sponsors").await), Vec::<&str>::new());
+
+      let (_, headers, _) = get("/clubs/club-1").await;
+      assert_eq!(headers["X-Data-Stale"], "true");
+      assert!(headers["X-Data-Age"].to_str().unwrap().parse::<u64>().is_ok());
+      let (_, headers, _) = get("/sponsors").await;
+      assert_eq!(headers["X-Data-Stale"], "false");
+      assert!(headers["X-Data-Age"].to_str().unwrap().parse::<u64>().unwrap() < 60);
+
+      let freshness: Value = get_admin_json("/admin/freshness").await;
+      assert_eq!(freshness["club"]["stale"], true);
+      assert!(freshness["club"]["error"].as_str().unwrap().contains("500"));
+      assert!(freshness["club"]["age"].is_number());
+      assert_eq!(freshness["sponsor"]["stale"], false);
+      assert!(freshness["sponsor"]["error"].is_null());
+
+      mock_notion.reset(workspace(mock_notion));
+      update_all(SyncMode::Incremental).await;
+      assert_eq!(get("/clubs").await.1["X-Data-Stale"], "false");
+    }
+  );
+}
+
+#[test]
+fn incremental_sync_merges_edits_and_drops_deleted_rows() {
+  run(
+    |mock_notion| async move {
+      let mut state: MockState = workspace(mock_notion);
+      mock_notion.reset(workspace(mock_notion));
+      update_all(SyncMode::Full).await;
+
+      let generation: u64 = CacheStorage::get().snapshot().generation;
+      update_all(SyncMode::Incremental).await;
+      assert_eq!(CacheStorage::get().snapshot().generation, generation, "unchanged rows were republished");
+      assert!(
+        mock_notion
+          .requests()
+          .iter()
+          .any(|request| request == "POST /v1/databases/member-database/query since 2023-08-02T00:00:00.000Z")
+      );
+
+      let members: &mut Vec<Value> = state.databases
+        .get_mut(&pages::database_id(&NotionDataType::Member))
+        .unwrap();
+      members[1] = pages::member("member-2", "Bobby", &["group-1"], None, &mock_notion.file_url("image.png"));
+      pages::edit(&mut members[1], "2023-08-03T00:00:00.000Z");
+      let mut club: Value = pages::club("club-2", "New club", &mock_notion.file_url("image.png"));
+      pages::edit(&mut club, "2023-08-03T00:00:00.000Z");
+      state.databases
+        .get_mut(&pages::database_id(&NotionDataType::Club))
+        .unwrap()
+        .push(club);
+      state.databases
+        .get_mut(&pages::database_id(&NotionDataType::Group))
+        .unwrap()
+        .retain(|group| group["id"] != "group-2");
+      mock_notion.reset(state);
+
+      update_all(SyncMode::Incremental).await;
+
+      assert_eq!(get_json("/members/member-2").await["name"], "Bobby");
+      assert_eq!(ids(&get_json("/groups/group-1").await["members"]), ["member-1", "member-2"]);
+      assert_eq!(ids(&get_json("/clubs").await), ["club-1", "club-2"]);
+      assert_eq!(ids(&get_json("/groups").await), ["group-1", "group-2"]);
+
+      update_all(SyncMode::IncrementalWithScan).await;
+
+      assert_eq!(ids(&get_json("/groups").await), ["group-1"]);
+      assert_eq!(ids(&get_json("/members/member-3").await["groups"]), Vec::<&str>::new());
+      assert!(
+        mock_notion
+          .requests()
+          .iter()
+          .any(|request| request == "POST /v1/databases/member-database/query since 2023-08-03T00:00:00.000Z")
+      );
+    }
+  );
+}
+
+#[test]
+fn incremental_sync_reports_skipped_rows() {
+  run(
+    |mock_notion| async move {
+      mock_notion.reset(workspace(mock_notion));
+      update_all(SyncMode::Full).await;
+
+      let mut state: MockState = workspace(mock_notion);
+      let members: &mut Vec<Value> = state.databases
+        .get_mut(&pages::database_id(&NotionDataType::Member))
+        .unwrap();
+      members[1]["properties"]
+        .as_object_mut()
+        .unwrap()
+        .remove("name");
+      pages::edit(&mut members[1], "2023-08-03T00:00:00.000Z");
+      mock_notion.reset(state);
+
+      update_all(SyncMode::Incremental).await;
+
+      assert_eq!(ids(&get_json("/members").await), ["member-1", "member-3"]);
+      let report: Value = get_admin_json("/admin/sync-report").await;
+      assert_eq!(report["member"]["parsed"], 2);
+      assert_eq!(report["member"]["skipped"][0]["page_id"], "member-2");
+      assert_eq!(report["member"]["skipped"][0]["field"], "properties.name");
+
+      let mut state: MockState = workspace(mock_notion);
+      pages::edit(
+        &mut state.databases
+          .get_mut(&pages::database_id(&NotionDataType::Member))
+          .unwrap()[1],
+        "2023-08-04T00:00:00.000Z"
+      );
+      mock_notion.reset(state);
+
+      update_all(SyncMode::Incremental).await;
+
+      assert_eq!(ids(&get_json("/members").await), ["member-1", "member-2", "member-3"]);
+      let report: Value = get_admin_json("/admin/sync-report").await;
+      assert_eq!(report["member"]["parsed"], 3);
+      assert_eq!(report["member"]["skipped"], json!([]));
+    }
+  );
+}
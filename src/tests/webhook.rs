@@ -0,0 +1,154 @@
+use std::time::Duration;
+
+use axum::{
+  body::Body,
+  http::{Request, StatusCode}
+};
+use hmac::{Hmac, Mac};
+use serde_json::{Value, json};
+use sha2::Sha256;
+use tokio::time::{sleep, Instant};
+
+use crate::notion::{client::SyncMode, types::NotionDataType};
+
+use super::{
+  run,
+  send,
+  get,
+  get_json,
+  ids,
+  WEBHOOK_SECRET,
+  mock_notion::MockState,
+  pages,
+  sync::{update_all, workspace}
+};
+
+
+fn event(event_type: &str, page_id: &str, database_id: &str) -> Value {
+  json!(
+    {
+      "id": format!("event-{page_id}"),
+      "timestamp": "2023-08-03T00:00:00.000Z",
+      "type": event_type,
+      "entity": {"id": page_id, "type": "page"},
+      "data": {"parent": {"id": database_id, "type": "database"}}
+    }
+  )
+}
+
+async fn post_event(event: &Value, secret: &str) -> StatusCode {
+  let body: Vec<u8> = serde_json::to_vec(event).unwrap();
+
+  let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+  mac.update(&body);
+  let signature: String = format!("sha256={}", hex::encode(mac.finalize().into_bytes()));
+
+  send(
+    Request::post("/webhooks/notion")
+      .header("Content-Type", "application/json")
+      .header("X-Notion-Signature", signature)
+      .body(Body::from(body))
+      .unwrap()
+  ).await.0
+}
+
+
+#[test]
+fn webhook_events_refresh_the_affected_page_once_per_burst() {
+  run(
+    |mock_notion| async move {
+      let member_id: &str = "4e5f6a7b-8c9d-4e0f-8a1b-2c3d4e5f6a7b";
+      let image_url: String = mock_notion.file_url("image.png");
+      let with_member = |name: &str| -> MockState {
+        let mut state: MockState = workspace(mock_notion);
+        state.databases
+          .get_mut(&pages::database_id(&NotionDataType::Member))
+          .unwrap()
+          .push(pages::member(member_id, name, &["group-1"], None, &image_url));
+        state.databases
+          .get_mut(&pages::database_id(&NotionDataType::Group))
+          .unwrap()[0] = pages::group("group-1", "Core", &["member-1", member_id]);
+        state
+      };
+
+      mock_notion.reset(with_member("Dave"));
+      update_all(SyncMode::Full).await;
+
+      let mut state: MockState = with_member("David");
+      pages::edit(
+        state.databases
+          .get_mut(&pages::database_id(&NotionDataType::Member))
+          .unwrap()
+          .last_mut()
+          .unwrap(),
+        "2023-08-03T00:00:00.000Z"
+      );
+      mock_notion.reset(state);
+
+      let member_database: String = pages::database_id(&NotionDataType::Member);
+      let edit: Value = event("page.properties_updated", member_id, &member_database);
+
+      assert_eq!(post_event(&edit, "wrong-secret").await, StatusCode::UNAUTHORIZED);
+      for ignored in [
+        event("page.created", "5f6a7b8c-9d0e-4f1a-8b2c-3d4e5f6a7b8c", "unknown-database"),
+        event("page.created", "../databases/member-database", &member_database)
+      ] {
+        assert_eq!(post_event(&ignored, WEBHOOK_SECRET).await, StatusCode::OK);
+      }
+      for _ in 0..3 {
+        assert_eq!(post_event(&edit, WEBHOOK_SECRET).await, StatusCode::OK);
+      }
+
+      let member_path: String = format!("/members/{member_id}");
+      let deadline: Instant = Instant::now() + Duration::from_secs(10);
+      while get_json(&member_path).await["name"] != "David" {
+        assert!(Instant::now() < deadline, "webhook did not refresh members");
+        sleep(Duration::from_millis(50)).await;
+      }
+
+      // Only the edited page is fetched, once; its avatar is already mirrored.
+      assert_eq!(mock_notion.requests(), [format!("GET /v1/pages/{member_id}")]);
+      assert_eq!(ids(&get_json("/groups/group-1").await["members"]), [member_id, "member-1"]);
+      assert_eq!(get_json("/groups/group-1").await["members"][1]["name"], "David");
+
+      let mut state: MockState = with_member("David");
+      pages::archive(
+        state.databases
+          .get_mut(&pages::database_id(&NotionDataType::Member))
+          .unwrap()
+          .last_mut()
+          .unwrap()
+      );
+      mock_notion.reset(state);
+
+      let delete: Value = event("page.deleted", member_id, &member_database);
+      assert_eq!(post_event(&delete, WEBHOOK_SECRET).await, StatusCode::OK);
+
+      let deadline: Instant = Instant::now() + Duration::from_secs(10);
+      while get(&member_path).await.0 != StatusCode::NOT_FOUND {
+        assert!(Instant::now() < deadline, "webhook did not remove the member");
+        sleep(Duration::from_millis(50)).await;
+      }
+      assert_eq!(ids(&get_json("/groups/group-1").await["members"]), ["member-1"]);
+    }
+  );
+}
+
+#[test]
+fn webhook_verification_requests_are_refused_once_a_secret_is_set() {
+  run(
+    |_| async move {
+      let (status, _, _) = send(
+        Request::post("/webhooks/notion")
+          .body(Body::from(r#"{"verification_token": "secret_token"}"#))
+          .unwrap()
+      ).await;
+      assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+      let (status, _, _) = send(
+        Request::post("/webhooks/notion").body(Body::from("not json")).unwrap()
+      ).await;
+      assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+  );
+}